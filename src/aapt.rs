@@ -44,7 +44,7 @@ pub fn get_version_code(metadata: &str) -> Option<u32> {
 ///
 /// returns [None] if the name couldn't be found
 pub fn get_name(metadata: &str) -> Option<String> {
-  let regex = Regex::new(r"name='((?:[[:alpha:]]|\.)+)'").unwrap();
+  let regex = Regex::new(r"name='([\w.]+)'").unwrap();
 
   // apply regext to string
   let captures = regex.captures(metadata)?;
@@ -53,3 +53,21 @@ pub fn get_name(metadata: &str) -> Option<String> {
 
   Some(name.as_str().to_string())
 }
+
+/// gets all requested permissions (and their max sdk version) from an apk metadata string
+pub fn get_permissions(metadata: &str) -> Vec<(String, Option<u32>)> {
+  let regex =
+    Regex::new(r"(?m)^uses-permission: name='([^']+)'(?: maxSdkVersion='(\d+)')?").unwrap();
+
+  regex
+    .captures_iter(metadata)
+    .map(|captures| {
+      (
+        captures[1].to_string(),
+        captures
+          .get(2)
+          .and_then(|max_sdk| max_sdk.as_str().parse().ok()),
+      )
+    })
+    .collect()
+}
@@ -0,0 +1,521 @@
+//! Command line interface for managing an fdroid repository
+//!
+//! Prints human readable output by default and json with `--json`.
+//!
+//! ## Exit codes
+//! | Code | Meaning                                              |
+//! |------|------------------------------------------------------|
+//! | 0    | success                                              |
+//! | 1    | `lint` found errors or `audit` found issues          |
+//! | 2    | invalid arguments                                    |
+//! | 10   | [Error::File]                                        |
+//! | 11   | [Error::YAMLConvert]                                 |
+//! | 12   | [Error::JsonConvert]                                 |
+//! | 13   | [Error::NotADirectory]                               |
+//! | 14   | [Error::NotAFile]                                    |
+//! | 15   | [Error::Init]                                        |
+//! | 16   | [Error::Update]                                      |
+//! | 17   | [Error::Run]                                         |
+//! | 18   | [Error::InvalidFile]                                 |
+//! | 19   | [Error::MissingConfig]                               |
+//! | 20   | [Error::PackageNotFound]                             |
+//! | 21   | [Error::Http]                                        |
+//! | 22   | [Error::FingerprintMismatch]                         |
+//! | 23   | [Error::Integrity]                                   |
+//! | 24   | [Error::UploadPolicy]                                |
+//! | 25   | [Error::UpdateCheck]                                 |
+//! | 26   | [Error::Build]                                       |
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand};
+use fdroid::error::{Error, InvalidFile, Result};
+use fdroid::metadata::AppMetadata;
+use fdroid::{App, Config, LintSeverity, Repository, RepositoryEvent};
+use serde::Serialize;
+use serde_json::json;
+use uuid::Uuid;
+
+#[derive(Parser)]
+#[command(
+  name = "fdroid-rs",
+  version,
+  about = "Create and manipulate an fdroid repository"
+)]
+struct Cli {
+  /// directory of the repository
+  #[arg(short, long, global = true, default_value = ".")]
+  repo: PathBuf,
+  /// print json instead of human readable output
+  #[arg(long, global = true)]
+  json: bool,
+  #[command(subcommand)]
+  command: Commands,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+  /// Initialize a new repository (does nothing if it already exists)
+  Init,
+  /// Add signed apks
+  Add { apks: Vec<PathBuf> },
+  /// Sign apks with the repository key and add them
+  Sign { apks: Vec<PathBuf> },
+  /// Delete an apk
+  Rm { apk_name: String },
+  /// List all apps
+  Ls,
+  /// Show an app and all of its versions
+  Show { package_name: String },
+  /// Read or change the metadata of an app
+  #[command(subcommand)]
+  Metadata(MetadataCommands),
+  /// Read or change the configuration of the repository
+  #[command(subcommand)]
+  Config(ConfigCommands),
+  /// Change the repository icon
+  #[command(subcommand)]
+  Image(ImageCommands),
+  /// Lint the metadata of all apps
+  Lint,
+  /// Check that all files match the index
+  Audit,
+  /// Propose the metadata of an app from a local Android project
+  Import {
+    project_dir: PathBuf,
+    /// save the proposed metadata instead of only printing it
+    #[arg(long)]
+    save: bool,
+    /// replace existing metadata of the app when saving
+    #[arg(long, requires = "save")]
+    force: bool,
+  },
+  /// Check the source repository of an app for a new version and update its metadata
+  Checkupdates {
+    package_name: String,
+    /// add builds for a new version (if `AutoUpdateMode` is `Version`)
+    #[arg(long)]
+    auto: bool,
+  },
+  /// Build a version of an app with `fdroid build` and put the apk into `unsigned/`
+  Build {
+    package_name: String,
+    version_code: u64,
+    /// sign the apk with the repository key and add it
+    #[arg(long)]
+    sign: bool,
+  },
+  /// Delete all apps and metadata
+  Clear {
+    /// confirm that everything should be deleted
+    #[arg(long, required = true)]
+    yes: bool,
+  },
+}
+
+#[derive(Subcommand)]
+enum MetadataCommands {
+  /// Print the metadata of an app
+  Get { package_name: String },
+  /// Replace the metadata of an app with a yaml or json file
+  Set { package_name: String, file: PathBuf },
+  /// Edit the metadata of an app with `$EDITOR`
+  Edit { package_name: String },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+  /// Print the configuration or a single value of it
+  Get { key: Option<String> },
+  /// Change a single value of the configuration (`null` removes it)
+  Set { key: String, value: String },
+}
+
+#[derive(Subcommand)]
+enum ImageCommands {
+  /// Replace the repository icon (has to have the same file type)
+  Set { file: PathBuf },
+}
+
+/// Result of a command, printed as json or in a human readable way
+enum Output {
+  /// nothing to print except a confirmation
+  Done(String),
+  Apps(Vec<App>),
+  App(Box<App>),
+  Yaml(serde_json::Value),
+  Lint(fdroid::LintReport),
+  Audit(fdroid::AuditReport),
+}
+
+fn main() -> ExitCode {
+  let cli = Cli::parse();
+
+  match run(&cli) {
+    Ok(output) => {
+      let success = match &output {
+        Output::Lint(report) => report.is_ok(),
+        Output::Audit(report) => report.is_ok(),
+        _ => true,
+      };
+
+      print_output(output, cli.json);
+
+      if success {
+        ExitCode::SUCCESS
+      } else {
+        ExitCode::from(1)
+      }
+    }
+    Err(err) => {
+      if cli.json {
+        println!(
+          "{}",
+          json!({ "error": err.kind(), "message": err.to_string() })
+        );
+      } else {
+        eprintln!("error: {err}");
+      }
+
+      ExitCode::from(exit_code(&err))
+    }
+  }
+}
+
+fn run(cli: &Cli) -> Result<Output> {
+  // only init creates a new repository, all other commands need an existing one
+  let repository = if matches!(cli.command, Commands::Init) {
+    Repository::new(cli.repo.clone())?
+  } else {
+    Repository::open(cli.repo.clone())?
+  };
+
+  Ok(match &cli.command {
+    Commands::Init => Output::Done(format!("Initialized repository in {:?}", cli.repo)),
+    Commands::Add { apks } => {
+      for apk in apks {
+        repository.add_app(apk)?;
+      }
+      Output::Done(format!("Added {} apks", apks.len()))
+    }
+    Commands::Sign { apks } => {
+      for apk in apks {
+        repository.sign_app(apk)?;
+      }
+      Output::Done(format!("Signed and added {} apks", apks.len()))
+    }
+    Commands::Rm { apk_name } => {
+      repository.delete_app(apk_name)?;
+      Output::Done(format!("Deleted {apk_name}"))
+    }
+    Commands::Ls => Output::Apps(repository.apps()?),
+    Commands::Show { package_name } => {
+      let mut app = repository
+        .apps()?
+        .into_iter()
+        .chain(repository.archived_apps()?)
+        .find(|app| &app.package_name == package_name)
+        .ok_or(Error::PackageNotFound {
+          package_name: package_name.clone(),
+          version_code: None,
+        })?;
+      app.packages = repository.all_versions(package_name)?;
+      Output::App(Box::new(app))
+    }
+    Commands::Metadata(MetadataCommands::Get { package_name }) => {
+      Output::Yaml(to_value(&repository.metadata(package_name)?)?)
+    }
+    Commands::Metadata(MetadataCommands::Set { package_name, file }) => {
+      let metadata = read_metadata(file)?;
+      repository.set_metadata(package_name, &metadata)?;
+      Output::Done(format!("Saved metadata of {package_name}"))
+    }
+    Commands::Metadata(MetadataCommands::Edit { package_name }) => {
+      edit_metadata(&repository, package_name)?;
+      Output::Done(format!("Saved metadata of {package_name}"))
+    }
+    Commands::Config(ConfigCommands::Get { key }) => {
+      let config = to_value(&repository.config()?)?;
+      match key {
+        Some(key) => Output::Yaml(
+          config
+            .get(key)
+            .cloned()
+            .ok_or(Error::MissingConfig(key.clone()))?,
+        ),
+        None => Output::Yaml(config),
+      }
+    }
+    Commands::Config(ConfigCommands::Set { key, value }) => {
+      let config = with_config_value(to_value(&repository.config()?)?, key, value)?;
+      repository.set_config(&config)?;
+      Output::Done(format!("Set {key}"))
+    }
+    Commands::Image(ImageCommands::Set { file }) => {
+      repository.set_image(file)?;
+      Output::Done("Replaced the repository icon".to_owned())
+    }
+    Commands::Lint => Output::Lint(repository.lint()?),
+    Commands::Audit => Output::Audit(repository.audit()?),
+    Commands::Import {
+      project_dir,
+      save,
+      force,
+    } => {
+      let project = fdroid::import_project(project_dir)?;
+      for warning in &project.warnings {
+        eprintln!("Warning: {warning}");
+      }
+      if *save {
+        let invalid =
+          |reason: &str| Error::InvalidFile(InvalidFile::with_reason(project_dir.clone(), reason));
+        let package_name = project
+          .package_name
+          .ok_or(invalid("No package name found"))?;
+        if !fdroid::is_valid_package_name(&package_name) {
+          return Err(invalid(&format!(
+            "\"{package_name}\" is not a valid package name"
+          )));
+        }
+        let metadata_path = repository.package_metadata_path(&package_name);
+        if metadata_path.exists() && !*force {
+          return Err(Error::InvalidFile(InvalidFile::with_reason(
+            metadata_path,
+            "The metadata already exists, use --force to replace it",
+          )));
+        }
+        repository.set_metadata(&package_name, &project.metadata)?;
+        Output::Done(format!("Saved metadata of {package_name}"))
+      } else {
+        Output::Yaml(to_value(&project.metadata)?)
+      }
+    }
+    Commands::Checkupdates { package_name, auto } => {
+      match repository.check_updates(package_name)? {
+        Some(update_check) => {
+          let mut value = to_value(&update_check)?;
+          if *auto {
+            value["builds"] = to_value(&repository.auto_update(package_name, &update_check)?)?;
+          }
+          Output::Yaml(value)
+        }
+        None => Output::Done(format!("{package_name} is not checked for updates")),
+      }
+    }
+    Commands::Build {
+      package_name,
+      version_code,
+      sign,
+    } => {
+      let mut repository = repository.clone();
+      repository.add_observer(Arc::new(|event: &RepositoryEvent| {
+        if let RepositoryEvent::BuildLog { line, .. } = event {
+          eprintln!("{line}");
+        }
+      }));
+
+      let report = repository.build(package_name, *version_code)?;
+      if *sign {
+        repository.sign_app(&report.apk_path)?;
+      }
+      Output::Yaml(to_value(&report)?)
+    }
+    Commands::Clear { .. } => {
+      repository.clear()?;
+      Output::Done("Deleted all apps and metadata".to_owned())
+    }
+  })
+}
+
+/// Sets a field of the config, converting the value to the type of the field
+///
+/// String fields always keep the value as a string (e.g. `repo_name=2024`). Other fields (and
+/// unset ones, whose type is unknown) are parsed as json first and used as a string otherwise.
+fn with_config_value(mut config: serde_json::Value, key: &str, value: &str) -> Result<Config> {
+  let entry = config
+    .get(key)
+    .ok_or(Error::MissingConfig(key.to_owned()))?;
+
+  let candidates = if entry.is_string() {
+    vec![json!(value)]
+  } else {
+    serde_json::from_str(value)
+      .ok()
+      .into_iter()
+      .chain([json!(value)])
+      .collect()
+  };
+
+  let mut error = String::new();
+  for candidate in candidates {
+    config[key] = candidate;
+    match serde_json::from_value(config.clone()) {
+      Ok(config) => return Ok(config),
+      Err(err) => error = err.to_string(),
+    }
+  }
+
+  Err(Error::JsonConvert(format!("{key}: {error}")))
+}
+
+fn print_output(output: Output, json: bool) {
+  if json {
+    let value = match output {
+      Output::Done(message) => json!({ "message": message }),
+      Output::Apps(apps) => json!(apps),
+      Output::App(app) => json!(app),
+      Output::Yaml(value) => value,
+      Output::Lint(report) => json!(report),
+      Output::Audit(report) => json!(report),
+    };
+    println!(
+      "{}",
+      serde_json::to_string_pretty(&value).unwrap_or_default()
+    );
+    return;
+  }
+
+  match output {
+    Output::Done(message) => println!("{message}"),
+    Output::Apps(apps) => {
+      for app in apps {
+        println!(
+          "{}\t{}\t{}",
+          app.package_name, app.suggested_version_code, app.name
+        );
+      }
+    }
+    Output::App(app) => {
+      println!("{} ({})", app.name, app.package_name);
+      println!("License: {}", app.license);
+      println!("Suggested version code: {}", app.suggested_version_code);
+      println!("Versions:");
+      for package in app.packages {
+        println!(
+          "  {}\t{}\t{}\t{:?}",
+          package
+            .version_code
+            .map(|version_code| version_code.to_string())
+            .unwrap_or_default(),
+          package.version_name,
+          package.apk_name,
+          package.location
+        );
+      }
+    }
+    Output::Yaml(mut value) => {
+      // unset fields are only noise for humans
+      remove_nulls(&mut value);
+      print!("{}", serde_yaml::to_string(&value).unwrap_or_default())
+    }
+    Output::Lint(report) => {
+      for issue in &report.issues {
+        let severity = match issue.severity {
+          LintSeverity::Warning => "warning",
+          LintSeverity::Error => "error",
+        };
+        println!(
+          "{severity}: {} {}: {}",
+          issue.package_name, issue.field, issue.message
+        );
+      }
+      if report.issues.is_empty() {
+        println!("No issues found");
+      }
+    }
+    Output::Audit(report) => {
+      for issue in &report.issues {
+        println!("{issue}");
+      }
+      println!(
+        "Checked {} packages, found {} issues",
+        report.checked_packages,
+        report.issues.len()
+      );
+    }
+  }
+}
+
+/// Reads metadata from a yaml or json file (depending on the extension)
+fn read_metadata(file: &Path) -> Result<AppMetadata> {
+  let content = fs::read_to_string(file)?;
+
+  if file
+    .extension()
+    .is_some_and(|extension| extension == "json")
+  {
+    serde_json::from_str(&content).map_err(|err| Error::JsonConvert(err.to_string()))
+  } else {
+    serde_yaml::from_str(&content).map_err(Error::from)
+  }
+}
+
+/// Opens the metadata in `$EDITOR` and saves it afterwards
+fn edit_metadata(repository: &Repository, package_name: &str) -> Result<()> {
+  let editor = std::env::var("EDITOR").unwrap_or("vi".to_owned());
+  let file = std::env::temp_dir().join(format!("{package_name}-{}.yml", Uuid::new_v4()));
+
+  fs::write(
+    &file,
+    serde_yaml::to_string(&repository.metadata(package_name)?)?,
+  )?;
+
+  let result = Command::new(&editor)
+    .arg(&file)
+    .status()
+    .map_err(|_| Error::Run(format!("{editor} {file:?}")))
+    .and_then(|status| {
+      if status.success() {
+        read_metadata(&file)
+      } else {
+        Err(Error::Run(format!("{editor} {file:?}")))
+      }
+    })
+    .and_then(|metadata| repository.set_metadata(package_name, &metadata));
+
+  fs::remove_file(&file)?;
+
+  result
+}
+
+/// Removes all fields whose value is `null`, including the ones of nested objects
+fn remove_nulls(value: &mut serde_json::Value) {
+  match value {
+    serde_json::Value::Object(object) => {
+      object.retain(|_, value| !value.is_null());
+      object.values_mut().for_each(remove_nulls);
+    }
+    serde_json::Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+    _ => {}
+  }
+}
+
+fn to_value(value: &impl Serialize) -> Result<serde_json::Value> {
+  serde_json::to_value(value).map_err(|err| Error::JsonConvert(err.to_string()))
+}
+
+/// Maps the error kinds to exit codes (see the table at the top)
+fn exit_code(error: &Error) -> u8 {
+  match error {
+    Error::File(_) => 10,
+    Error::YAMLConvert(_) => 11,
+    Error::JsonConvert(_) => 12,
+    Error::NotADirectory(_) => 13,
+    Error::NotAFile(_) => 14,
+    Error::Init => 15,
+    Error::Update => 16,
+    Error::Run(_) => 17,
+    Error::InvalidFile(_) => 18,
+    Error::MissingConfig(_) => 19,
+    Error::PackageNotFound { .. } => 20,
+    Error::Http(_) => 21,
+    Error::FingerprintMismatch { .. } => 22,
+    Error::Integrity(_) => 23,
+    Error::UploadPolicy(_) => 24,
+    Error::UpdateCheck(_) => 25,
+    Error::Build(_) => 26,
+  }
+}
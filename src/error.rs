@@ -38,6 +38,63 @@ pub enum Error {
   /// Contains the command
   Run(String),
   InvalidFile(InvalidFile),
+  /// Gets thrown when a required value is missing in the config file
+  ///
+  /// Contains the name of the missing key
+  MissingConfig(String),
+  /// Gets thrown when a package (or a specific version of it) does not exist
+  PackageNotFound {
+    package_name: String,
+    version_code: Option<u64>,
+  },
+  /// Gets thrown when an http request fails
+  ///
+  /// Contains the url and the reason
+  Http(String),
+  /// Gets thrown when a signature does not belong to the expected (pinned) certificate
+  FingerprintMismatch {
+    expected: String,
+    actual: String,
+  },
+  /// Gets thrown when a downloaded or stored file does not match its index entry
+  Integrity(IntegrityError),
+  /// Gets thrown when an upload violates the [`crate::UploadPolicy`] of the repository
+  UploadPolicy(PolicyViolation),
+  /// Gets thrown when checking an app for updates fails
+  ///
+  /// Contains the reason (prefixed with the package name, if known)
+  UpdateCheck(String),
+  /// Gets thrown when building an app fails
+  ///
+  /// Contains the reason (prefixed with the package name and version code)
+  Build(String),
+}
+
+impl Error {
+  /// Returns the name of the variant, e.g. `"NotAFile"`
+  ///
+  /// Useful to report errors in a machine readable way (e.g. in json responses).
+  pub fn kind(&self) -> &'static str {
+    match self {
+      Error::File(_) => "File",
+      Error::YAMLConvert(_) => "YAMLConvert",
+      Error::JsonConvert(_) => "JsonConvert",
+      Error::NotADirectory(_) => "NotADirectory",
+      Error::NotAFile(_) => "NotAFile",
+      Error::Init => "Init",
+      Error::Update => "Update",
+      Error::Run(_) => "Run",
+      Error::InvalidFile(_) => "InvalidFile",
+      Error::MissingConfig(_) => "MissingConfig",
+      Error::PackageNotFound { .. } => "PackageNotFound",
+      Error::Http(_) => "Http",
+      Error::FingerprintMismatch { .. } => "FingerprintMismatch",
+      Error::Integrity(_) => "Integrity",
+      Error::UploadPolicy(_) => "UploadPolicy",
+      Error::UpdateCheck(_) => "UpdateCheck",
+      Error::Build(_) => "Build",
+    }
+  }
 }
 
 /// Struct for an [Error::InvalidFile] error.
@@ -66,6 +123,126 @@ impl InvalidFile {
   }
 }
 
+/// Reason why a file does not match its index entry
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum IntegrityError {
+  /// The file has an unexpected size
+  Size {
+    file: PathBuf,
+    expected: u64,
+    actual: u64,
+  },
+  /// The file has an unexpected hash
+  Hash {
+    file: PathBuf,
+    expected: String,
+    actual: String,
+  },
+  /// The hash type of the index entry is not supported (only sha256 is)
+  UnsupportedHashType { file: PathBuf, hash_type: String },
+}
+
+impl fmt::Display for IntegrityError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      IntegrityError::Size {
+        file,
+        expected,
+        actual,
+      } => write!(
+        f,
+        "{file:?} has a size of {actual} bytes instead of {expected} bytes"
+      ),
+      IntegrityError::Hash {
+        file,
+        expected,
+        actual,
+      } => write!(f, "{file:?} has the hash {actual} instead of {expected}"),
+      IntegrityError::UnsupportedHashType { file, hash_type } => {
+        write!(f, "Hash type \"{hash_type}\" of {file:?} is not supported")
+      }
+    }
+  }
+}
+
+/// Reason why an upload got rejected by the [`crate::UploadPolicy`]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PolicyViolation {
+  /// A package with the same version code already exists
+  Duplicate {
+    package_name: String,
+    version_code: u64,
+    /// name of the apk that already exists
+    existing_apk: String,
+  },
+  /// The version code is lower than the suggested version code of the app
+  Downgrade {
+    package_name: String,
+    version_code: u64,
+    suggested_version_code: u64,
+  },
+  /// The package name of the apk is not the expected one
+  PackageMismatch { expected: String, actual: String },
+  /// The apk introduces dangerous permissions which have not been approved
+  DangerousPermissions {
+    package_name: String,
+    version_code: u64,
+    /// all newly requested dangerous permissions
+    permissions: Vec<String>,
+  },
+  /// The apk contains trackers or proprietary libraries
+  Libraries {
+    package_name: String,
+    version_code: u64,
+    /// names of all detected libraries
+    libraries: Vec<String>,
+  },
+}
+
+impl fmt::Display for PolicyViolation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PolicyViolation::Duplicate {
+        package_name,
+        version_code,
+        existing_apk,
+      } => write!(
+        f,
+        "{package_name} with version code {version_code} already exists as \"{existing_apk}\""
+      ),
+      PolicyViolation::Downgrade {
+        package_name,
+        version_code,
+        suggested_version_code,
+      } => write!(
+        f,
+        "{package_name} with version code {version_code} is older than the suggested version code {suggested_version_code}"
+      ),
+      PolicyViolation::PackageMismatch { expected, actual } => {
+        write!(f, "Expected package {expected} but got {actual}")
+      }
+      PolicyViolation::DangerousPermissions {
+        package_name,
+        version_code,
+        permissions,
+      } => write!(
+        f,
+        "{package_name} with version code {version_code} requests unapproved dangerous permissions: {}",
+        permissions.join(", ")
+      ),
+      PolicyViolation::Libraries {
+        package_name,
+        version_code,
+        libraries,
+      } => write!(
+        f,
+        "{package_name} with version code {version_code} contains forbidden libraries: {}",
+        libraries.join(", ")
+      ),
+    }
+  }
+}
+
 impl fmt::Display for Error {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     match self {
@@ -87,6 +264,26 @@ impl fmt::Display for Error {
           .map(|reason| format!(" Reason: \"{reason}\"."))
           .unwrap_or(String::new())
       ),
+      Error::MissingConfig(key) => write!(f, "The config file does not contain \"{key}\"!"),
+      Error::PackageNotFound {
+        package_name,
+        version_code,
+      } => match version_code {
+        Some(version_code) => write!(
+          f,
+          "Package {package_name} with version code {version_code} does not exist!"
+        ),
+        None => write!(f, "Package {package_name} does not exist!"),
+      },
+      Error::Http(reason) => write!(f, "Http request failed: {reason}"),
+      Error::FingerprintMismatch { expected, actual } => write!(
+        f,
+        "Signed by an unexpected certificate. Expected fingerprint \"{expected}\" but got \"{actual}\"!"
+      ),
+      Error::Integrity(integrity_error) => write!(f, "Integrity check failed: {integrity_error}!"),
+      Error::UploadPolicy(violation) => write!(f, "Upload rejected: {violation}!"),
+      Error::UpdateCheck(reason) => write!(f, "Update check failed: {reason}!"),
+      Error::Build(reason) => write!(f, "Build failed: {reason}!"),
     }
   }
 }
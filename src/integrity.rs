@@ -0,0 +1,53 @@
+//! Module for checking files against the hash and size stored in the index
+
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, IntegrityError, Result};
+use crate::Package;
+
+/// Calculates the SHA-256 hash of a file (lowercase hex, same as in the index)
+pub fn file_sha256(file_path: &PathBuf) -> Result<String> {
+  let mut hasher = Sha256::new();
+  io::copy(&mut File::open(file_path)?, &mut hasher)?;
+
+  Ok(hex::encode(hasher.finalize()))
+}
+
+/// Checks that a file has the size and hash of a [Package]
+///
+/// # Error
+/// Returns [Error::Integrity] if the size or the hash do not match
+pub fn verify_package_file(file_path: &PathBuf, package: &Package) -> Result<()> {
+  let integrity_error = |error| Err(Error::Integrity(error));
+
+  if !package.hash_type.eq_ignore_ascii_case("sha256") {
+    return integrity_error(IntegrityError::UnsupportedHashType {
+      file: file_path.clone(),
+      hash_type: package.hash_type.clone(),
+    });
+  }
+
+  let size = file_path.metadata()?.len();
+  if size != package.size {
+    return integrity_error(IntegrityError::Size {
+      file: file_path.clone(),
+      expected: package.size,
+      actual: size,
+    });
+  }
+
+  let hash = file_sha256(file_path)?;
+  if !hash.eq_ignore_ascii_case(&package.hash) {
+    return integrity_error(IntegrityError::Hash {
+      file: file_path.clone(),
+      expected: package.hash.clone(),
+      actual: hash,
+    });
+  }
+
+  Ok(())
+}
@@ -0,0 +1,93 @@
+//! Module for verifying signed [jar](https://docs.oracle.com/javase/tutorial/deployment/jar/signing.html) files
+//! (like `index-v1.jar` or `entry.jar`) with `jarsigner` and `keytool`
+
+use std::fs::File;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process::Command;
+
+use base64::Engine;
+use log::debug;
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+
+use crate::error::{Error, InvalidFile, Result};
+
+/// Verifies the signature of a jar file and returns the SHA-256 fingerprint of the signer
+///
+/// The fingerprint has the same format as [`crate::Repository::fingerprint`].
+///
+/// # Error
+/// Returns an error if the jar is unsigned, contains unsigned entries or the signature is invalid
+pub fn verify_jar(jar_path: &PathBuf) -> Result<String> {
+  let invalid =
+    |reason: &str| Error::InvalidFile(InvalidFile::with_reason(jar_path.clone(), reason));
+
+  if !jar_path.is_file() {
+    return Err(Error::NotAFile(jar_path.clone()));
+  }
+
+  // force english output, as it has to be parsed
+  let output = Command::new("jarsigner")
+    .arg("-J-Duser.language=en")
+    .arg("-verify")
+    .arg(jar_path)
+    .output()
+    .map_err(|_| Error::Run(format!("jarsigner -verify {jar_path:?}")))?;
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+  debug!("jarsigner output: {stdout}");
+
+  if !output.status.success() || !stdout.contains("jar verified.") {
+    return Err(invalid("Invalid jar signature"));
+  }
+
+  if stdout.contains("unsigned entries") {
+    return Err(invalid("Jar contains unsigned entries"));
+  }
+
+  let output = Command::new("keytool")
+    .arg("-J-Duser.language=en")
+    .arg("-printcert")
+    .arg("-rfc")
+    .arg("-jarfile")
+    .arg(jar_path)
+    .output()
+    .map_err(|_| Error::Run(format!("keytool -printcert -rfc -jarfile {jar_path:?}")))?;
+
+  let stdout = String::from_utf8_lossy(&output.stdout);
+
+  // the first certificate belongs to the first signer
+  let certificate: String = stdout
+    .split("-----BEGIN CERTIFICATE-----")
+    .nth(1)
+    .and_then(|rest| rest.split("-----END CERTIFICATE-----").next())
+    .ok_or(invalid("No signer certificate found"))?
+    .chars()
+    .filter(|char| !char.is_whitespace())
+    .collect();
+
+  let certificate = base64::engine::general_purpose::STANDARD
+    .decode(certificate)
+    .map_err(|_| invalid("Invalid signer certificate"))?;
+
+  Ok(hex::encode_upper(Sha256::digest(certificate)))
+}
+
+/// Reads a single file from a jar (or any other zip archive)
+pub fn read_jar_entry(jar_path: &PathBuf, entry_name: &str) -> Result<String> {
+  let invalid =
+    |reason: String| Error::InvalidFile(InvalidFile::with_reason(jar_path.clone(), &reason));
+
+  let mut archive =
+    ZipArchive::new(File::open(jar_path)?).map_err(|err| invalid(err.to_string()))?;
+
+  let mut entry = archive
+    .by_name(entry_name)
+    .map_err(|err| invalid(format!("{entry_name}: {err}")))?;
+
+  let mut content = String::new();
+  entry.read_to_string(&mut content)?;
+
+  Ok(content)
+}
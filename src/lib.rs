@@ -40,16 +40,24 @@
 //! ```
 //! ## External Dependencies
 //! - [fdroidserver](https://gitlab.com/fdroid/fdroidserver)  
-//! For working with the repository itself
+//!   For working with the repository itself
 //! - [android-sdk-build-tools](https://developer.android.com/tools/releases/build-tools)  
-//! Uses [aapt](https://elinux.org/Android_aapt) for extracting metadata from apks
+//!   Uses [aapt](https://elinux.org/Android_aapt) for extracting metadata from apks
 //!
 //! ## Logging
 //! This crate uses the [log crate](https://docs.rs/log/latest/log/) to log all **write** changes.
 
 mod aapt;
 pub mod error;
+mod integrity;
+mod jar;
+mod remote;
 mod repository;
+#[cfg(feature = "server")]
+mod server;
 
 // Re-Export
+pub use remote::*;
 pub use repository::*;
+#[cfg(feature = "server")]
+pub use server::ApiServer;
@@ -0,0 +1,243 @@
+//! Client for reading published fdroid repositories
+//!
+//! Works the same way as the F-Droid client: the signed index is downloaded, its signature is
+//! verified against a pinned fingerprint and afterwards it is parsed into [App]s.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use log::{debug, info, warn};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::error::{Error, InvalidFile, Result};
+use crate::integrity::verify_package_file;
+use crate::jar::{read_jar_entry, verify_jar};
+use crate::{App, Package, PackageLocation};
+
+/// Client for a published (remote) repository
+///
+/// ```no_run
+/// # use fdroid::RemoteRepository;
+/// let remote = RemoteRepository::new(
+///   "https://f-droid.org/repo",
+///   "43238D512C1E5EB2D6569F4A3AFBF5523418B82E0A3ED1552770ABB9A9C9CCAB",
+/// );
+/// let apps = remote.apps().unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct RemoteRepository {
+  /// url of the repo directory (the one containing `index-v1.jar`), without trailing slash
+  base_url: String,
+  /// pinned SHA-256 fingerprint of the signing certificate
+  fingerprint: String,
+}
+
+impl RemoteRepository {
+  /// Creates a new client
+  ///
+  /// The fingerprint has the same format as [`crate::Repository::fingerprint`],
+  /// separators (`:` and spaces) and lowercase letters are accepted as well.
+  pub fn new(base_url: &str, fingerprint: &str) -> Self {
+    Self {
+      base_url: base_url.trim_end_matches('/').to_owned(),
+      fingerprint: normalize_fingerprint(fingerprint),
+    }
+  }
+
+  /// Returns the url of the repository
+  pub fn base_url(&self) -> &str {
+    &self.base_url
+  }
+
+  /// Returns the url of a file inside of the repository
+  pub fn url(&self, file_name: &str) -> String {
+    format!("{}/{}", self.base_url, file_name.trim_start_matches('/'))
+  }
+
+  /// Downloads, verifies and parses the index of the repository
+  ///
+  /// Uses `entry.jar` (index v2) and falls back to `index-v1.jar` if the repository does not
+  /// provide it.
+  ///
+  /// # Error
+  /// Returns an error if the index can't be downloaded, the signature is invalid or does not
+  /// match the pinned fingerprint or the index can't be parsed
+  pub fn apps(&self) -> Result<Vec<App>> {
+    match self.apps_v2() {
+      Err(Error::Http(reason)) => {
+        warn!("Could not get entry.jar ({reason}), falling back to index-v1.jar");
+        self.apps_v1()
+      }
+      result => result,
+    }
+  }
+
+  /// Downloads, verifies and parses `index-v1.jar`
+  pub fn apps_v1(&self) -> Result<Vec<App>> {
+    let index = self.verified_jar_entry("index-v1.jar", "index-v1.json")?;
+
+    App::from_json(&parse_json(&index)?, PackageLocation::Repo).ok_or(Error::JsonConvert(
+      "Could not map repository index file!".to_owned(),
+    ))
+  }
+
+  /// Downloads and verifies `entry.jar`, then downloads, verifies and parses the referenced
+  /// `index-v2.json`
+  pub fn apps_v2(&self) -> Result<Vec<App>> {
+    let entry = parse_json(&self.verified_jar_entry("entry.jar", "entry.json")?)?;
+
+    let index_entry = entry.get("index").ok_or(Error::JsonConvert(
+      "entry.json contains no index".to_owned(),
+    ))?;
+    let index_name =
+      index_entry
+        .get("name")
+        .and_then(|val| val.as_str())
+        .ok_or(Error::JsonConvert(
+          "entry.json contains no index name".to_owned(),
+        ))?;
+    let index_hash = index_entry
+      .get("sha256")
+      .and_then(|val| val.as_str())
+      .ok_or(Error::JsonConvert(
+        "entry.json contains no index hash".to_owned(),
+      ))?;
+
+    let index = self.download(index_name)?;
+
+    // the index itself is not signed, but its hash is part of the signed entry
+    let actual_hash = hex::encode(Sha256::digest(&index));
+    if !actual_hash.eq_ignore_ascii_case(index_hash) {
+      return Err(Error::InvalidFile(InvalidFile::with_reason(
+        PathBuf::from(index_name),
+        &format!("Expected hash {index_hash} but got {actual_hash}"),
+      )));
+    }
+
+    App::from_json_v2(
+      &parse_json(&String::from_utf8_lossy(&index))?,
+      PackageLocation::Repo,
+    )
+    .ok_or(Error::JsonConvert(
+      "Could not map repository index file!".to_owned(),
+    ))
+  }
+
+  /// Downloads the apk of a [Package] into `destination`
+  ///
+  /// The apk is streamed into `<destination>.part` first. If that file already exists
+  /// (e.g. because a previous download was interrupted), the download is resumed.
+  /// Afterwards the size and the hash are checked against the index entry.
+  ///
+  /// # Error
+  /// Returns [Error::Integrity] if the downloaded file does not match the index entry.
+  /// In that case, the partial file is removed.
+  pub fn download_package(&self, package: &Package, destination: &PathBuf) -> Result<()> {
+    info!("Downloading {} to {destination:?}", package.apk_name);
+
+    let partial_path = partial_path(destination);
+    let existing_size = partial_path
+      .metadata()
+      .map(|metadata| metadata.len())
+      .unwrap_or(0);
+
+    if existing_size < package.size {
+      let url = self.url(&package.apk_name);
+      let mut request = ureq::get(&url);
+
+      if existing_size > 0 {
+        debug!("Resuming download at byte {existing_size}");
+        request = request.set("Range", &format!("bytes={existing_size}-"));
+      }
+
+      let response = request
+        .call()
+        .map_err(|err| Error::Http(format!("{url}: {err}")))?;
+
+      // the server might not support range requests and send the whole file
+      let mut file = if response.status() == 206 {
+        OpenOptions::new().append(true).open(&partial_path)?
+      } else {
+        File::create(&partial_path)?
+      };
+
+      io::copy(&mut response.into_reader(), &mut file)?;
+    }
+
+    match verify_package_file(&partial_path, package) {
+      Ok(()) => {
+        fs::rename(&partial_path, destination)?;
+        Ok(())
+      }
+      Err(err) => {
+        warn!("Removing invalid download {partial_path:?}");
+        fs::remove_file(&partial_path)?;
+        Err(err)
+      }
+    }
+  }
+
+  /// Downloads a file of the repository into memory
+  pub(crate) fn download(&self, file_name: &str) -> Result<Vec<u8>> {
+    let url = self.url(file_name);
+    debug!("Downloading {url}");
+
+    let response = ureq::get(&url)
+      .call()
+      .map_err(|err| Error::Http(format!("{url}: {err}")))?;
+
+    let mut content = vec![];
+    response.into_reader().read_to_end(&mut content)?;
+
+    Ok(content)
+  }
+
+  /// Downloads a jar, verifies it against the pinned fingerprint and returns the content of
+  /// one of its entries
+  fn verified_jar_entry(&self, jar_name: &str, entry_name: &str) -> Result<String> {
+    info!("Fetching {jar_name} from {}", self.base_url);
+
+    let jar_path = std::env::temp_dir().join(format!("fdroid-{}-{jar_name}", Uuid::new_v4()));
+    fs::write(&jar_path, self.download(jar_name)?)?;
+
+    let result = verify_jar(&jar_path).and_then(|fingerprint| {
+      if fingerprint == self.fingerprint {
+        read_jar_entry(&jar_path, entry_name)
+      } else {
+        Err(Error::FingerprintMismatch {
+          expected: self.fingerprint.clone(),
+          actual: fingerprint,
+        })
+      }
+    });
+
+    fs::remove_file(&jar_path)?;
+
+    result
+  }
+}
+
+/// Returns the path a download is written to until it has been verified
+pub(crate) fn partial_path(destination: &Path) -> PathBuf {
+  let mut file_name = destination.file_name().unwrap_or_default().to_owned();
+  file_name.push(".part");
+
+  destination.with_file_name(file_name)
+}
+
+/// Removes separators from a fingerprint and converts it to uppercase
+fn normalize_fingerprint(fingerprint: &str) -> String {
+  fingerprint
+    .chars()
+    .filter(|char| char.is_ascii_hexdigit())
+    .collect::<String>()
+    .to_uppercase()
+}
+
+/// Parses a json string
+fn parse_json(json: &str) -> Result<serde_json::Value> {
+  serde_json::from_str(json)
+    .map_err(|_| Error::JsonConvert("Could not read repository index file!".to_owned()))
+}
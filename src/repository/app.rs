@@ -6,7 +6,7 @@
 //!
 //! A Package is a single [apk](https://en.wikipedia.org/wiki/Apk_(file_format))
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{
   fs::{self, File},
   io::Read,
@@ -14,16 +14,18 @@ use std::{
 
 use crate::aapt::*;
 use crate::error::{Error, InvalidFile, Result};
-use crate::metadata::Category;
+use crate::integrity::verify_package_file;
+use crate::metadata::{AntiFeature, Category};
+use crate::remote::partial_path;
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 
-use super::Repository;
+use super::{Repository, RepositoryEvent};
 
 /// [DTO](https://en.wikipedia.org/wiki/Data_transfer_object) for a single app.
 ///
 /// Get a List of all Apps by calling [Repository::apps].
-#[derive(Clone, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct App {
   /// the name of the package
   pub package_name: String,
@@ -37,6 +39,12 @@ pub struct App {
   pub license: String,
   /// the name of the app
   pub name: String,
+  /// short description of the app (english if available)
+  pub summary: Option<String>,
+  /// full description of the app (english if available)
+  pub description: Option<String>,
+  /// the anti-features of the app, unknown anti-features are skipped
+  pub anti_features: Vec<AntiFeature>,
   /// when the app was added
   pub added: i64,
   /// when the app was last updated
@@ -49,7 +57,10 @@ impl App {
   /// Reads a Json Value and tries to extract all fields to create a list of apps
   ///
   /// returns None if any field can't be converted
-  fn from_json(value: &serde_json::Value) -> Option<Vec<Self>> {
+  pub(crate) fn from_json(
+    value: &serde_json::Value,
+    location: PackageLocation,
+  ) -> Option<Vec<Self>> {
     // get both lists
     let apps = value.get("apps")?;
     let packages = value.get("packages")?;
@@ -65,6 +76,9 @@ impl App {
       let last_updated = app.get("lastUpdated")?.as_i64()?.to_owned();
       let added = app.get("added")?.as_i64()?.to_owned();
 
+      let summary = localized_field(app, "summary");
+      let description = localized_field(app, "description");
+
       let mut categories = vec![];
 
       // get all categories (are saved in a map)
@@ -75,17 +89,31 @@ impl App {
         );
       }
 
+      let anti_features = app
+        .get("antiFeatures")
+        .and_then(|val| val.as_array())
+        .map(|anti_features| {
+          anti_features
+            .iter()
+            .filter_map(|anti_feature| AntiFeature::deserialize(anti_feature).ok())
+            .collect()
+        })
+        .unwrap_or_default();
+
       let mut packages_vec = vec![];
 
       let package = packages.get(&package_name)?;
 
       // map all package fields
       for package_entry in package.as_array()? {
-        packages_vec.push(Package::from_json(package_entry)?);
+        packages_vec.push(Package::from_json(package_entry, location)?);
       }
 
       apps_vec.push(App {
         name,
+        summary,
+        description,
+        anti_features,
         suggested_version_code,
         license,
         package_name,
@@ -98,10 +126,136 @@ impl App {
 
     Some(apps_vec)
   }
+
+  /// Reads a Json Value of an `index-v2.json` file and tries to extract all fields to create a
+  /// list of apps
+  ///
+  /// As `index-v2.json` does not contain a suggested version code, the highest version code is used.
+  ///
+  /// returns None if any field can't be converted
+  pub(crate) fn from_json_v2(
+    value: &serde_json::Value,
+    location: PackageLocation,
+  ) -> Option<Vec<Self>> {
+    let mut apps_vec = vec![];
+
+    for (package_name, package) in value.get("packages")?.as_object()? {
+      let metadata = package.get("metadata")?;
+
+      let name = localized(metadata.get("name")?)?;
+      let summary = metadata.get("summary").and_then(localized);
+      let description = metadata.get("description").and_then(localized);
+      let license = metadata
+        .get("license")
+        .and_then(|val| val.as_str())
+        .unwrap_or_default()
+        .to_owned();
+      let added = metadata.get("added")?.as_i64()?;
+      let last_updated = metadata.get("lastUpdated")?.as_i64()?;
+
+      let mut categories = vec![];
+
+      for category in metadata
+        .get("categories")
+        .and_then(|val| val.as_array())
+        .unwrap_or(&vec![])
+      {
+        categories.push(
+          Category::deserialize(category)
+            .unwrap_or(Category::Custom(category.as_str()?.to_string())),
+        );
+      }
+
+      let mut packages_vec = vec![];
+      let mut anti_features = vec![];
+
+      for version in package.get("versions")?.as_object()?.values() {
+        packages_vec.push(Package::from_json_v2(package_name, version, location)?);
+
+        // anti-features are stored per version, mapped to their localized reason
+        for name in version
+          .get("antiFeatures")
+          .and_then(|val| val.as_object())
+          .into_iter()
+          .flat_map(|anti_features| anti_features.keys())
+        {
+          if let Ok(anti_feature) = AntiFeature::deserialize(&serde_json::json!(name)) {
+            if !anti_features.contains(&anti_feature) {
+              anti_features.push(anti_feature);
+            }
+          }
+        }
+      }
+      anti_features.sort();
+
+      // newest version first (same as index-v1.json)
+      packages_vec.sort_by_key(|package| std::cmp::Reverse(package.version_code));
+
+      let suggested_version_code = packages_vec
+        .first()
+        .and_then(|package| package.version_code)
+        .map(|version_code| version_code.to_string())
+        .unwrap_or_default();
+
+      apps_vec.push(App {
+        name,
+        summary,
+        description,
+        anti_features,
+        suggested_version_code,
+        license,
+        package_name: package_name.clone(),
+        last_updated,
+        added,
+        packages: packages_vec,
+        categories,
+      });
+    }
+
+    Some(apps_vec)
+  }
+}
+
+/// Returns a field of an `index-v1.json` app, which is either set directly or localized
+fn localized_field(app: &serde_json::Value, key: &str) -> Option<String> {
+  app
+    .get(key)
+    .and_then(|val| val.as_str())
+    .map(|val| val.to_owned())
+    .or_else(|| {
+      let locales = app.get("localized")?.as_object()?;
+
+      locales
+        .get("en-US")
+        .and_then(|locale| locale.get(key))
+        .or_else(|| locales.values().find_map(|locale| locale.get(key)))?
+        .as_str()
+        .map(|val| val.to_owned())
+    })
+}
+
+/// Returns the english (or else the first) entry of a localized json map
+fn localized(value: &serde_json::Value) -> Option<String> {
+  let map = value.as_object()?;
+
+  map
+    .get("en-US")
+    .or_else(|| map.values().next())?
+    .as_str()
+    .map(|val| val.to_owned())
+}
+
+/// Directory a [Package] is stored in
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, Eq, PartialEq)]
+pub enum PackageLocation {
+  /// The package is in [Repository::repo_path]
+  Repo,
+  /// The package has been archived and is in [Repository::archive_path]
+  Archive,
 }
 
 /// [DTO](https://en.wikipedia.org/wiki/Data_transfer_object) for a specific version of a single app (So mostly an apk).
-#[derive(Clone, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Package {
   // Exist
   pub added: i64,
@@ -112,6 +266,7 @@ pub struct Package {
   pub size: u64,
   pub version_name: String,
   // Can be Missing
+  pub features: Vec<String>,
   pub nativecode: Vec<String>,
   pub max_sdk_version: Option<u32>,
   pub min_sdk_version: Option<u32>,
@@ -120,13 +275,16 @@ pub struct Package {
   pub target_sdk_version: Option<u32>,
   pub uses_permission: Vec<(String, Option<u32>)>,
   pub version_code: Option<u64>,
+  // Not part of the index
+  /// whether the package is in the repository or in the archive
+  pub location: PackageLocation,
 }
 
 impl Package {
   /// Reads a Json Value and tries to extract all fields to create an instance of Package
   ///
   /// returns None if any field can't be converted
-  fn from_json(value: &serde_json::Value) -> Option<Self> {
+  fn from_json(value: &serde_json::Value, location: PackageLocation) -> Option<Self> {
     // Always Exist
     let added = value.get("added")?.as_i64()?;
     let apk_name = value.get("apkName")?.as_str()?.to_owned();
@@ -147,6 +305,16 @@ impl Package {
       .and_then(|val| val.as_u64())
       .and_then(|val| val.try_into().ok());
 
+    let mut features = vec![];
+
+    for feature_entry in value
+      .get("features")
+      .and_then(|val| val.as_array())
+      .unwrap_or(&vec![])
+    {
+      features.push(feature_entry.as_str()?.to_owned());
+    }
+
     let mut nativecode = vec![];
 
     for nativecode_entry in value
@@ -194,6 +362,7 @@ impl Package {
     Some(Self {
       added,
       apk_name,
+      features,
       hash,
       hash_type,
       max_sdk_version,
@@ -207,6 +376,97 @@ impl Package {
       uses_permission,
       version_code,
       version_name,
+      location,
+    })
+  }
+}
+
+impl Package {
+  /// Reads a single version of an `index-v2.json` file
+  ///
+  /// returns None if any field can't be converted
+  fn from_json_v2(
+    package_name: &str,
+    value: &serde_json::Value,
+    location: PackageLocation,
+  ) -> Option<Self> {
+    let file = value.get("file")?;
+    let manifest = value.get("manifest")?;
+
+    let as_u32 = |val: Option<&serde_json::Value>| -> Option<u32> {
+      val
+        .and_then(|val| val.as_u64())
+        .and_then(|val| val.try_into().ok())
+    };
+
+    let uses_sdk = manifest.get("usesSdk");
+
+    let mut uses_permission = vec![];
+
+    for permission in manifest
+      .get("usesPermission")
+      .and_then(|val| val.as_array())
+      .unwrap_or(&vec![])
+    {
+      uses_permission.push((
+        permission.get("name")?.as_str()?.to_owned(),
+        as_u32(permission.get("maxSdkVersion")),
+      ));
+    }
+
+    let mut features = vec![];
+
+    for feature in manifest
+      .get("features")
+      .and_then(|val| val.as_array())
+      .unwrap_or(&vec![])
+    {
+      features.push(feature.get("name")?.as_str()?.to_owned());
+    }
+
+    let mut nativecode = vec![];
+
+    for nativecode_entry in manifest
+      .get("nativecode")
+      .and_then(|val| val.as_array())
+      .unwrap_or(&vec![])
+    {
+      nativecode.push(nativecode_entry.as_str()?.to_owned());
+    }
+
+    let signer = manifest
+      .get("signer")
+      .and_then(|signer| signer.get("sha256"))
+      .and_then(|sha256| sha256.get(0))
+      .and_then(|val| val.as_str())
+      .map(|val| val.to_owned());
+
+    Some(Self {
+      added: value.get("added")?.as_i64()?,
+      apk_name: file
+        .get("name")?
+        .as_str()?
+        .trim_start_matches('/')
+        .to_owned(),
+      features,
+      hash: file.get("sha256")?.as_str()?.to_owned(),
+      hash_type: "sha256".to_owned(),
+      max_sdk_version: as_u32(manifest.get("maxSdkVersion")),
+      min_sdk_version: as_u32(uses_sdk.and_then(|uses_sdk| uses_sdk.get("minSdkVersion"))),
+      nativecode,
+      package_name: package_name.to_owned(),
+      sig: None,
+      signer,
+      size: file.get("size")?.as_u64()?,
+      target_sdk_version: as_u32(uses_sdk.and_then(|uses_sdk| uses_sdk.get("targetSdkVersion"))),
+      uses_permission,
+      version_code: manifest.get("versionCode").and_then(|val| val.as_u64()),
+      version_name: manifest
+        .get("versionName")
+        .and_then(|val| val.as_str())
+        .unwrap_or_default()
+        .to_owned(),
+      location,
     })
   }
 }
@@ -216,29 +476,108 @@ impl Repository {
   ///
   /// Returns an error if the json file can't be mapped correctly
   pub fn apps(&self) -> Result<Vec<App>> {
-    let index_file = self.repo_path().join("index-v1.json");
+    self.read_index(PackageLocation::Repo)
+  }
+
+  /// Reads the index file of the archive and returns all archived apps
+  ///
+  /// Each app only contains the packages which have been archived.
+  ///
+  /// Returns an error if the json file can't be mapped correctly
+  pub fn archived_apps(&self) -> Result<Vec<App>> {
+    self.read_index(PackageLocation::Archive)
+  }
+
+  /// Returns all versions of a package, both from the repository and the archive
+  ///
+  /// The packages are sorted by their version code, newest first.
+  pub fn all_versions(&self, package_name: &str) -> Result<Vec<Package>> {
+    let mut packages: Vec<Package> = self
+      .apps()?
+      .into_iter()
+      .chain(self.archived_apps()?)
+      .filter(|app| app.package_name == package_name)
+      .flat_map(|app| app.packages)
+      .collect();
+
+    packages.sort_by_key(|package| std::cmp::Reverse(package.version_code));
+
+    Ok(packages)
+  }
+
+  /// Copies the apk of a [Package] into `destination` and checks its size and hash
+  ///
+  /// Like [RemoteRepository::download_package](crate::RemoteRepository::download_package), the
+  /// apk is copied to `<destination>.part` first and only moved to `destination` once it has
+  /// been verified.
+  ///
+  /// # Error
+  /// Returns [Error::Integrity](crate::error::Error::Integrity) if the stored apk does not match
+  /// the index entry. In that case, the copied file is removed and `destination` is unchanged.
+  pub fn download_package(&self, package: &Package, destination: &PathBuf) -> Result<()> {
+    let source = match package.location {
+      PackageLocation::Repo => self.repo_path(),
+      PackageLocation::Archive => self.archive_path(),
+    }
+    .join(&package.apk_name);
+
+    if !source.is_file() {
+      return Err(Error::NotAFile(source));
+    }
+
+    let partial_path = partial_path(destination);
+    fs::copy(&source, &partial_path)?;
+
+    match verify_package_file(&partial_path, package) {
+      Ok(()) => {
+        fs::rename(&partial_path, destination)?;
+        Ok(())
+      }
+      Err(err) => {
+        warn!("Removing invalid copy {partial_path:?}");
+        fs::remove_file(&partial_path)?;
+        Err(err)
+      }
+    }
+  }
+
+  /// Reads the `index-v1.json` file of either the repository or the archive
+  fn read_index(&self, location: PackageLocation) -> Result<Vec<App>> {
+    let index_file = match location {
+      PackageLocation::Repo => self.repo_path(),
+      PackageLocation::Archive => self.archive_path(),
+    }
+    .join("index-v1.json");
 
     if !index_file.exists() {
       // if no index file exists, no apps exist
       return Ok(vec![]);
     }
 
-    let mut file = File::open(index_file)?;
-    let mut file_content = String::new();
-    file.read_to_string(&mut file_content)?;
-
-    App::from_json(
-      &serde_json::from_str(&file_content)
-        .map_err(|_| Error::JsonConvert("Could not read repository index file!".to_owned()))?,
-    )
-    .ok_or(Error::JsonConvert(
-      "Could not map repository index file!".to_owned(),
-    ))
+    self.cache.get_or_insert("index", &index_file, || {
+      let mut file = File::open(&index_file)?;
+      let mut file_content = String::new();
+      file.read_to_string(&mut file_content)?;
+
+      App::from_json(
+        &serde_json::from_str(&file_content)
+          .map_err(|_| Error::JsonConvert("Could not read repository index file!".to_owned()))?,
+        location,
+      )
+      .ok_or(Error::JsonConvert(
+        "Could not map repository index file!".to_owned(),
+      ))
+    })
   }
 
   /// adds an app directly to the app repository
+  ///
+  /// # Error
+  /// Returns an error if the apk violates the [UploadPolicy](super::UploadPolicy)
   pub fn add_app(&self, file_path: &PathBuf) -> Result<()> {
     info!("Adding new app: {file_path:?}");
+    self.check_upload_policy(file_path)?;
+
     // save file
     let new_file_path = self.repo_path().join(
       file_path
@@ -262,11 +601,33 @@ impl Repository {
     // cleanup if error
     if update_result.is_err() && new_file_path.exists() && new_file_path.is_file() {
       fs::remove_file(new_file_path)?;
+    } else if update_result.is_ok() {
+      self.emit_app_added(&new_file_path);
     }
 
     Ok(())
   }
 
+  /// Sends [RepositoryEvent::AppAdded] with the index entry of a newly added apk
+  fn emit_app_added(&self, apk_path: &Path) {
+    let apk_name = apk_path.file_name().unwrap_or_default().to_string_lossy();
+
+    let package = self.apps().ok().and_then(|apps| {
+      apps
+        .into_iter()
+        .flat_map(|app| app.packages)
+        .find(|package| package.apk_name == apk_name)
+    });
+
+    match package {
+      Some(package) => self.emit(RepositoryEvent::AppAdded {
+        package: package.package_name,
+        version_code: package.version_code,
+      }),
+      None => warn!("\"{apk_name}\" is missing in the index, no event is sent"),
+    }
+  }
+
   /// Deletes an apk (if it exists)
   pub fn delete_app(&self, apk_name: &str) -> Result<()> {
     warn!("Deleting \"{apk_name}\"");
@@ -280,7 +641,13 @@ impl Repository {
         fs::remove_file(file_path)?;
 
         // update metadata
-        self.update()
+        self.update()?;
+
+        self.emit(RepositoryEvent::AppDeleted {
+          apk_name: apk_name.to_owned(),
+        });
+
+        Ok(())
       } else {
         Err(Error::NotAFile(file_path))
       }
@@ -293,12 +660,16 @@ impl Repository {
   /// Signs an apk and adds it
   ///
   /// - parses apk metadata
-  /// - add apk to unsigned folder
+  /// - checks the [UploadPolicy](super::UploadPolicy)
+  /// - add apk to unsigned folder (unless it is already there, e.g. after
+  ///   [Repository::build](super::Repository::build))
   /// - signs apk
   pub fn sign_app(&self, file_path: &PathBuf) -> Result<()> {
     info!("Singing {file_path:?}");
+    self.check_upload_policy(file_path)?;
+
     // get apk metadata
-    let apk_metadata = get_apk_info(file_path)?;
+    let apk_metadata = self.apk_info(file_path)?;
 
     // get version and name
     let apk_version = get_version_code(&apk_metadata).ok_or(Error::InvalidFile(
@@ -314,7 +685,10 @@ impl Repository {
       .unsigned_path()?
       .join(format!("{}_{}.apk", apk_name, apk_version));
 
-    fs::copy(file_path, new_file_path)?;
+    // copying a file onto itself would truncate it
+    if file_path.canonicalize()? != new_file_path.canonicalize().unwrap_or_default() {
+      fs::copy(file_path, new_file_path)?;
+    }
 
     // check if metadata exists
     let metadata = self.metadata(&apk_name);
@@ -329,6 +703,11 @@ impl Repository {
     // run fdroid update
     self.update()?;
 
+    self.emit(RepositoryEvent::AppSigned {
+      package: apk_name,
+      version_code: Some(apk_version.into()),
+    });
+
     Ok(())
   }
 }
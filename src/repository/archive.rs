@@ -0,0 +1,183 @@
+//! Extension of Repository used to move old apks into the archive
+//!
+//! See [archive_older](https://f-droid.org/en/docs/Setup_an_F-Droid_App_Repo/) and
+//! [ArchivePolicy](https://f-droid.org/en/docs/Build_Metadata_Reference/#ArchivePolicy)
+
+use std::fs;
+use std::path::Path;
+
+use log::{info, warn};
+
+use crate::error::{Error, Result};
+
+use super::{Package, Repository};
+
+/// Number of versions kept in the repository if `archive_older` is not set (same as fdroidserver)
+pub const DEFAULT_ARCHIVE_OLDER: u8 = 3;
+
+/// Files that belong to an apk and have to be moved together with it
+const COMPANION_EXTENSIONS: [&str; 3] = ["asc", "sig", "idsig"];
+
+impl Repository {
+  /// Returns all packages that should be moved to the archive
+  ///
+  /// The number of versions kept per app is taken from [`crate::metadata::AppMetadata::ArchivePolicy`]
+  /// and falls back to [`Config::archive_older`](super::Config::archive_older)
+  /// (or [DEFAULT_ARCHIVE_OLDER] if it is not set).
+  /// If `archive_older` is `0`, archiving is disabled. An `ArchivePolicy` of `0` archives all
+  /// versions of the app.
+  pub fn archive_candidates(&self) -> Result<Vec<Package>> {
+    let archive_older = self
+      .config()?
+      .archive_older
+      .unwrap_or(DEFAULT_ARCHIVE_OLDER);
+
+    if archive_older == 0 {
+      return Ok(vec![]);
+    }
+
+    let mut candidates = vec![];
+
+    for app in self.apps()? {
+      // per-app override
+      let keep = self
+        .metadata(&app.package_name)
+        .ok()
+        .and_then(|metadata| metadata.ArchivePolicy)
+        .unwrap_or(archive_older.into());
+
+      let mut packages = app.packages;
+      // newest version first
+      packages.sort_by_key(|package| std::cmp::Reverse(package.version_code));
+
+      candidates.extend(packages.into_iter().skip(keep as usize));
+    }
+
+    Ok(candidates)
+  }
+
+  /// Moves all packages beyond the retention count into [`Repository::archive_path`]
+  ///
+  /// See [`Repository::archive_candidates`] for how the retention count is determined.
+  /// Afterwards the repository is updated, which regenerates the index files of both the repo
+  /// and the archive.
+  ///
+  /// Returns the names of all apks that have been archived.
+  ///
+  /// # Error
+  /// Returns an error if the files can't be moved or the update fails.
+  /// In that case, all moved files are moved back.
+  pub fn apply_archive_policy(&self) -> Result<Vec<String>> {
+    let candidates = self.archive_candidates()?;
+
+    if candidates.is_empty() {
+      return Ok(vec![]);
+    }
+
+    fs::create_dir_all(self.archive_path())?;
+
+    let mut archived = vec![];
+
+    for package in candidates {
+      info!("Archiving \"{}\"", package.apk_name);
+
+      if let Err(err) = move_apk(&self.repo_path(), &self.archive_path(), &package.apk_name) {
+        self.rollback_archive(&archived);
+        return Err(err);
+      }
+
+      archived.push(package.apk_name);
+    }
+
+    if let Err(err) = self.update() {
+      self.rollback_archive(&archived);
+      return Err(err);
+    }
+
+    Ok(archived)
+  }
+
+  /// Moves an apk from the archive back into the repository
+  ///
+  /// If the apk is beyond the retention count, [`crate::metadata::AppMetadata::ArchivePolicy`]
+  /// of the app is raised first, as `fdroid update` would archive it again otherwise.
+  pub fn restore_archived(&self, apk_name: &str) -> Result<()> {
+    info!("Restoring \"{apk_name}\" from the archive");
+
+    let archived_file = self.archive_path().join(apk_name);
+
+    if !archived_file.is_file() {
+      return Err(Error::NotAFile(archived_file));
+    }
+
+    let package = self
+      .archived_apps()?
+      .into_iter()
+      .flat_map(|app| app.packages)
+      .find(|package| package.apk_name == apk_name);
+
+    match &package {
+      Some(package) => self.keep_in_repository(package)?,
+      None => warn!("\"{apk_name}\" is not in the archive index, it may be archived again"),
+    }
+
+    move_apk(&self.archive_path(), &self.repo_path(), apk_name)?;
+
+    self.update()
+  }
+
+  /// Raises the `ArchivePolicy` of an app, so a package is not beyond the retention count
+  fn keep_in_repository(&self, package: &Package) -> Result<()> {
+    let newer_versions = self
+      .apps()?
+      .into_iter()
+      .filter(|app| app.package_name == package.package_name)
+      .flat_map(|app| app.packages)
+      .filter(|newer| newer.version_code > package.version_code)
+      .count();
+    let keep = u32::try_from(newer_versions + 1).unwrap_or(u32::MAX);
+
+    let mut metadata = self.metadata(&package.package_name).unwrap_or_default();
+    let archive_older = self
+      .config()?
+      .archive_older
+      .unwrap_or(DEFAULT_ARCHIVE_OLDER);
+    if metadata.ArchivePolicy.unwrap_or(archive_older.into()) >= keep {
+      return Ok(());
+    }
+
+    info!(
+      "Raising the ArchivePolicy of {} to {keep}",
+      package.package_name
+    );
+    metadata.ArchivePolicy = Some(keep);
+    fs::create_dir_all(self.metadata_path())?;
+    self.set_metadata(&package.package_name, &metadata)
+  }
+
+  /// Moves already archived apks back into the repository
+  fn rollback_archive(&self, apk_names: &[String]) {
+    for apk_name in apk_names {
+      warn!("Moving \"{apk_name}\" back into the repository");
+
+      if move_apk(&self.archive_path(), &self.repo_path(), apk_name).is_err() {
+        warn!("Could not move \"{apk_name}\" back into the repository!");
+      }
+    }
+  }
+}
+
+/// Moves an apk and its signature files from one directory into another
+fn move_apk(from: &Path, to: &Path, apk_name: &str) -> Result<()> {
+  fs::rename(from.join(apk_name), to.join(apk_name))?;
+
+  for extension in COMPANION_EXTENSIONS {
+    let file_name = format!("{apk_name}.{extension}");
+
+    if from.join(&file_name).is_file() {
+      fs::rename(from.join(&file_name), to.join(&file_name))?;
+    }
+  }
+
+  Ok(())
+}
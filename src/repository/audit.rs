@@ -0,0 +1,473 @@
+//! Checks whether the files of a [Repository] match its index
+//!
+//! Meant to be run regularly (e.g. nightly) to detect corrupted or tampered files.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::integrity::file_sha256;
+use crate::jar::{read_jar_entry, verify_jar};
+
+use super::{App, PackageLocation, Repository};
+
+/// Keys of localized graphics inside of `index-v1.json` and `index-v2.json`
+const GRAPHICS: [&str; 4] = ["icon", "featureGraphic", "promoGraphic", "tvBanner"];
+
+/// Keys of localized screenshot lists inside of `index-v1.json`
+const SCREENSHOTS: [&str; 5] = [
+  "phoneScreenshots",
+  "sevenInchScreenshots",
+  "tenInchScreenshots",
+  "tvScreenshots",
+  "wearScreenshots",
+];
+
+/// A single problem found by [Repository::audit]
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub enum AuditIssue {
+  /// The size of an apk differs from its index entry
+  SizeMismatch {
+    index: PathBuf,
+    file: PathBuf,
+    expected: u64,
+    actual: u64,
+  },
+  /// The hash of an apk differs from its index entry
+  HashMismatch {
+    index: PathBuf,
+    file: PathBuf,
+    expected: String,
+    actual: String,
+  },
+  /// The index uses a hash type this crate can't check
+  UnsupportedHashType {
+    index: PathBuf,
+    file: PathBuf,
+    hash_type: String,
+  },
+  /// The index references a file that does not exist
+  MissingFile { index: PathBuf, file: PathBuf },
+  /// An apk exists but is not referenced by any index
+  NotInIndex { file: PathBuf },
+  /// An icon, graphic or screenshot referenced by the index does not exist
+  MissingImage {
+    index: PathBuf,
+    package_name: String,
+    file: PathBuf,
+  },
+  /// An index exists, but the signed jar containing it does not
+  UnsignedIndex { index: PathBuf, jar: PathBuf },
+  /// The signature of an index jar is invalid
+  InvalidSignature { jar: PathBuf, reason: String },
+  /// The index jar has been signed with a different key than the repository key
+  FingerprintMismatch {
+    jar: PathBuf,
+    expected: String,
+    actual: String,
+  },
+  /// `index-v2.json` does not match the hash or size recorded in the `entry.json` of `entry.jar`
+  IndexMismatch {
+    index: PathBuf,
+    jar: PathBuf,
+    reason: String,
+  },
+}
+
+impl fmt::Display for AuditIssue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AuditIssue::SizeMismatch {
+        file,
+        expected,
+        actual,
+        ..
+      } => write!(
+        f,
+        "{file:?} has a size of {actual} bytes instead of {expected} bytes"
+      ),
+      AuditIssue::HashMismatch {
+        file,
+        expected,
+        actual,
+        ..
+      } => write!(f, "{file:?} has the hash {actual} instead of {expected}"),
+      AuditIssue::UnsupportedHashType {
+        file, hash_type, ..
+      } => write!(f, "Hash type \"{hash_type}\" of {file:?} is not supported"),
+      AuditIssue::MissingFile { index, file } => {
+        write!(f, "{file:?} is referenced by {index:?} but does not exist")
+      }
+      AuditIssue::NotInIndex { file } => write!(f, "{file:?} is not part of any index"),
+      AuditIssue::MissingImage {
+        package_name, file, ..
+      } => write!(f, "Image {file:?} of {package_name} does not exist"),
+      AuditIssue::UnsignedIndex { index, jar } => {
+        write!(f, "{index:?} exists but {jar:?} does not")
+      }
+      AuditIssue::InvalidSignature { jar, reason } => {
+        write!(f, "Signature of {jar:?} is invalid: {reason}")
+      }
+      AuditIssue::FingerprintMismatch {
+        jar,
+        expected,
+        actual,
+      } => write!(
+        f,
+        "{jar:?} is signed by {actual} instead of the repository key {expected}"
+      ),
+      AuditIssue::IndexMismatch { index, jar, reason } => {
+        write!(f, "{index:?} does not match {jar:?}: {reason}")
+      }
+    }
+  }
+}
+
+/// Result of [Repository::audit]
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct AuditReport {
+  /// number of apks whose size and hash have been checked
+  pub checked_packages: usize,
+  pub issues: Vec<AuditIssue>,
+}
+
+impl AuditReport {
+  /// Returns true if no issues have been found
+  pub fn is_ok(&self) -> bool {
+    self.issues.is_empty()
+  }
+}
+
+impl Repository {
+  /// Checks that the repository and the archive match their indexes
+  ///
+  /// - recomputes size and hash of every apk in `index-v1.json` and `index-v2.json`
+  /// - reports index entries without a file and apks which are not in any index
+  /// - checks that all icons, graphics and screenshots of the index exist
+  /// - verifies the signature of `index-v1.jar` and `entry.jar` against [Repository::fingerprint]
+  ///
+  /// The fingerprint comparison is skipped if the keystore is not available.
+  ///
+  /// # Error
+  /// Returns an error if an index can't be read or a file can't be hashed.
+  /// Problems with the repository itself are part of the [AuditReport].
+  pub fn audit(&self) -> Result<AuditReport> {
+    info!("Auditing repository {:?}", self.path);
+
+    let fingerprint = self
+      .fingerprint()
+      .inspect_err(|err| warn!("Not checking index fingerprints: {err}"))
+      .ok();
+
+    let mut report = AuditReport::default();
+
+    for location in [PackageLocation::Repo, PackageLocation::Archive] {
+      let directory = match location {
+        PackageLocation::Repo => self.repo_path(),
+        PackageLocation::Archive => self.archive_path(),
+      };
+
+      if directory.is_dir() {
+        audit_directory(&directory, location, fingerprint.as_deref(), &mut report)?;
+      }
+    }
+
+    if report.is_ok() {
+      info!("Audit found no issues");
+    } else {
+      warn!("Audit found {} issues", report.issues.len());
+    }
+
+    Ok(report)
+  }
+}
+
+/// Audits either the repository or the archive directory
+fn audit_directory(
+  directory: &Path,
+  location: PackageLocation,
+  fingerprint: Option<&str>,
+  report: &mut AuditReport,
+) -> Result<()> {
+  let mut referenced = BTreeSet::new();
+  let mut hashes = BTreeMap::new();
+
+  for (index_name, jar_name) in [
+    ("index-v1.json", "index-v1.jar"),
+    ("index-v2.json", "entry.jar"),
+  ] {
+    let index_path = directory.join(index_name);
+    if !index_path.is_file() {
+      continue;
+    }
+
+    let index: serde_json::Value = serde_json::from_str(&fs::read_to_string(&index_path)?)
+      .map_err(|err| Error::JsonConvert(format!("{index_name}: {err}")))?;
+
+    let apps = if index_name == "index-v1.json" {
+      App::from_json(&index, location)
+    } else {
+      App::from_json_v2(&index, location)
+    }
+    .ok_or(Error::JsonConvert(format!("Could not map {index_name}")))?;
+
+    for package in apps.iter().flat_map(|app| &app.packages) {
+      let file = directory.join(&package.apk_name);
+      referenced.insert(file.clone());
+
+      if !file.is_file() {
+        report.issues.push(AuditIssue::MissingFile {
+          index: index_path.clone(),
+          file,
+        });
+        continue;
+      }
+
+      if !package.hash_type.eq_ignore_ascii_case("sha256") {
+        report.issues.push(AuditIssue::UnsupportedHashType {
+          index: index_path.clone(),
+          file,
+          hash_type: package.hash_type.clone(),
+        });
+        continue;
+      }
+
+      // both indexes reference the same apks, only hash them once
+      if !hashes.contains_key(&file) {
+        hashes.insert(file.clone(), (file.metadata()?.len(), file_sha256(&file)?));
+        report.checked_packages += 1;
+      }
+      let (size, hash) = &hashes[&file];
+
+      if *size != package.size {
+        report.issues.push(AuditIssue::SizeMismatch {
+          index: index_path.clone(),
+          file: file.clone(),
+          expected: package.size,
+          actual: *size,
+        });
+      }
+
+      if !hash.eq_ignore_ascii_case(&package.hash) {
+        report.issues.push(AuditIssue::HashMismatch {
+          index: index_path.clone(),
+          file,
+          expected: package.hash.clone(),
+          actual: hash.clone(),
+        });
+      }
+    }
+
+    for (package_name, file) in image_references(&index, directory) {
+      if !file.is_file() {
+        report.issues.push(AuditIssue::MissingImage {
+          index: index_path.clone(),
+          package_name,
+          file,
+        });
+      }
+    }
+
+    audit_signature(&index_path, &directory.join(jar_name), fingerprint, report);
+
+    // index-v2.json itself is not signed, only its hash and size inside of entry.json
+    if jar_name == "entry.jar" && directory.join(jar_name).is_file() {
+      if let Err(reason) = audit_index_entry(&index_path, &directory.join(jar_name)) {
+        report.issues.push(AuditIssue::IndexMismatch {
+          index: index_path.clone(),
+          jar: directory.join(jar_name),
+          reason,
+        });
+      }
+    }
+  }
+
+  for entry in fs::read_dir(directory)? {
+    let file = entry?.path();
+
+    if file.is_file()
+      && file.extension().is_some_and(|extension| extension == "apk")
+      && !referenced.contains(&file)
+    {
+      report.issues.push(AuditIssue::NotInIndex { file });
+    }
+  }
+
+  Ok(())
+}
+
+/// Verifies the jar an index has been published in
+fn audit_signature(
+  index_path: &Path,
+  jar_path: &Path,
+  fingerprint: Option<&str>,
+  report: &mut AuditReport,
+) {
+  if !jar_path.is_file() {
+    report.issues.push(AuditIssue::UnsignedIndex {
+      index: index_path.to_path_buf(),
+      jar: jar_path.to_path_buf(),
+    });
+    return;
+  }
+
+  match verify_jar(&jar_path.to_path_buf()) {
+    Ok(actual) => {
+      if let Some(expected) = fingerprint.filter(|expected| *expected != actual) {
+        report.issues.push(AuditIssue::FingerprintMismatch {
+          jar: jar_path.to_path_buf(),
+          expected: expected.to_owned(),
+          actual,
+        });
+      }
+    }
+    Err(err) => report.issues.push(AuditIssue::InvalidSignature {
+      jar: jar_path.to_path_buf(),
+      reason: err.to_string(),
+    }),
+  }
+}
+
+/// Compares `index-v2.json` with the hash and size recorded in `entry.jar`
+///
+/// Returns the reason if they don't match
+fn audit_index_entry(index_path: &Path, jar_path: &Path) -> std::result::Result<(), String> {
+  let entry = read_jar_entry(&jar_path.to_path_buf(), "entry.json")
+    .map_err(|err| err.to_string())
+    .and_then(|entry| {
+      serde_json::from_str::<serde_json::Value>(&entry).map_err(|err| err.to_string())
+    })?;
+  let index_entry = entry.get("index");
+
+  let expected_hash = index_entry
+    .and_then(|index| index.get("sha256"))
+    .and_then(|hash| hash.as_str())
+    .ok_or("entry.json contains no index hash")?;
+  let expected_size = index_entry
+    .and_then(|index| index.get("size"))
+    .and_then(|size| size.as_u64())
+    .ok_or("entry.json contains no index size")?;
+
+  let actual_size = index_path.metadata().map_err(|err| err.to_string())?.len();
+  if actual_size != expected_size {
+    return Err(format!(
+      "size of {actual_size} bytes instead of {expected_size} bytes"
+    ));
+  }
+
+  let actual_hash = file_sha256(&index_path.to_path_buf()).map_err(|err| err.to_string())?;
+  if !actual_hash.eq_ignore_ascii_case(expected_hash) {
+    return Err(format!("hash {actual_hash} instead of {expected_hash}"));
+  }
+
+  Ok(())
+}
+
+/// Returns all images referenced by an index, together with the package they belong to
+fn image_references(index: &serde_json::Value, directory: &Path) -> Vec<(String, PathBuf)> {
+  let mut references = vec![];
+  let as_str = |value: &serde_json::Value| value.as_str().map(|val| val.to_owned());
+
+  // index-v1.json: file names relative to the localized directory of the app
+  for app in index
+    .get("apps")
+    .and_then(|apps| apps.as_array())
+    .unwrap_or(&vec![])
+  {
+    let Some(package_name) = app.get("packageName").and_then(as_str) else {
+      continue;
+    };
+
+    // the main icon is stored in one of the icon directories
+    if let Some(icon) = app.get("icon").and_then(as_str) {
+      references.push((package_name.clone(), directory.join("icons").join(icon)));
+    }
+
+    for (locale, localized) in app
+      .get("localized")
+      .and_then(|localized| localized.as_object())
+      .into_iter()
+      .flatten()
+    {
+      let locale_path = directory.join(&package_name).join(locale);
+
+      for graphic in GRAPHICS {
+        if let Some(file_name) = localized.get(graphic).and_then(as_str) {
+          references.push((package_name.clone(), locale_path.join(file_name)));
+        }
+      }
+
+      for screenshots in SCREENSHOTS {
+        for file_name in localized
+          .get(screenshots)
+          .and_then(|files| files.as_array())
+          .into_iter()
+          .flatten()
+          .filter_map(as_str)
+        {
+          references.push((
+            package_name.clone(),
+            locale_path.join(screenshots).join(file_name),
+          ));
+        }
+      }
+    }
+  }
+
+  // index-v2.json: file entries with paths relative to the directory
+  for (package_name, package) in index
+    .get("packages")
+    .and_then(|packages| packages.as_object())
+    .into_iter()
+    .flatten()
+  {
+    let Some(metadata) = package.get("metadata") else {
+      continue;
+    };
+
+    let mut files: Vec<&serde_json::Value> = vec![];
+
+    for graphic in GRAPHICS {
+      files.extend(
+        metadata
+          .get(graphic)
+          .and_then(|localized| localized.as_object())
+          .into_iter()
+          .flat_map(|localized| localized.values()),
+      );
+    }
+
+    for screenshots in metadata
+      .get("screenshots")
+      .and_then(|screenshots| screenshots.as_object())
+      .into_iter()
+      .flat_map(|screenshots| screenshots.values())
+    {
+      files.extend(
+        screenshots
+          .as_object()
+          .into_iter()
+          .flat_map(|localized| localized.values())
+          .filter_map(|files| files.as_array())
+          .flatten(),
+      );
+    }
+
+    for name in files
+      .into_iter()
+      .filter_map(|file| file.get("name").and_then(as_str))
+    {
+      references.push((
+        package_name.clone(),
+        directory.join(name.trim_start_matches('/')),
+      ));
+    }
+  }
+
+  references
+}
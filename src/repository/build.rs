@@ -0,0 +1,293 @@
+//! Building apps from their [Builds](crate::metadata::Builds), similar to `fdroid build`
+//!
+//! The build itself is done by a [BuildBackend] (fdroidserver by default). Every line it prints
+//! is sent as [RepositoryEvent::BuildLog] and written to [Repository::build_log_path]. The built
+//! apk is placed into [Repository::unsigned_path], so it can be signed with [Repository::sign_app]:
+//! ```no_run
+//! # use std::path::PathBuf;
+//! # use fdroid::Repository;
+//! let repository = Repository::new(PathBuf::from("/fdroid")).unwrap();
+//! let report = repository.build("org.example.app", 12).unwrap();
+//! repository.sign_app(&report.apk_path).unwrap();
+//! ```
+
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+
+use log::{debug, info, warn};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+use super::{Repository, RepositoryEvent};
+
+/// The command which builds a single version of an app
+///
+/// It is run inside of the repository with `<package>:<version_code>` as the last argument.
+/// The apk has to be placed at `unsigned/<package>_<version_code>.apk` (like `fdroid build`)
+/// or `tmp/<package>_<version_code>.apk` (like `fdroid build --test`).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BuildBackend {
+  /// the executable, e.g. `fdroid`
+  pub program: String,
+  /// arguments before `<package>:<version_code>`, e.g. `build --verbose`
+  pub args: Vec<String>,
+}
+
+impl Default for BuildBackend {
+  fn default() -> Self {
+    Self::new("fdroid", &["build", "--verbose"])
+  }
+}
+
+impl BuildBackend {
+  pub fn new(program: &str, args: &[&str]) -> Self {
+    Self {
+      program: program.to_owned(),
+      args: args.iter().map(|arg| arg.to_string()).collect(),
+    }
+  }
+}
+
+/// Result of a successful [Repository::build]
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct BuildReport {
+  /// package name of the built app
+  pub package: String,
+  /// version code of the build inside of the metadata
+  pub version_code: u64,
+  /// the unsigned apk inside of [Repository::unsigned_path]
+  pub apk_path: PathBuf,
+  /// the complete output of the [BuildBackend]
+  pub log_path: PathBuf,
+}
+
+impl Repository {
+  /// Returns the current [BuildBackend]
+  pub fn build_backend(&self) -> &BuildBackend {
+    &self.build_backend
+  }
+
+  /// Sets the [BuildBackend] used for all further builds
+  pub fn set_build_backend(&mut self, build_backend: BuildBackend) {
+    self.build_backend = build_backend;
+  }
+
+  /// gets the file path of the log of a build
+  ///
+  /// does not check if the build has been run
+  pub fn build_log_path(&self, package_name: &str, version_code: u64) -> PathBuf {
+    self
+      .logs_path()
+      .join(format!("{package_name}_{version_code}.log"))
+  }
+
+  /// Builds a version of an app with the [BuildBackend]
+  ///
+  /// Sends [RepositoryEvent::BuildLog] for every line of output and
+  /// [RepositoryEvent::BuildFinished] at the end. The log is kept in
+  /// [Repository::build_log_path] even if the build fails. An apk of an earlier build of the
+  /// same version is replaced.
+  ///
+  /// # Error
+  /// Returns [Error::Build] if the metadata has no (enabled) build for the version code, the
+  /// backend reports a failure or no apk has been produced
+  pub fn build(&self, package_name: &str, version_code: u64) -> Result<BuildReport> {
+    info!("Building {package_name}:{version_code}");
+    let fail = |reason: String| Error::Build(format!("{package_name}:{version_code}: {reason}"));
+
+    let metadata = self.metadata(package_name)?;
+    let build = metadata
+      .Builds
+      .iter()
+      .flatten()
+      .find(|build| build.versionCode.as_deref() == Some(&version_code.to_string()))
+      .ok_or(fail("No build with this version code exists".to_owned()))?;
+    if let Some(reason) = &build.disable {
+      return Err(fail(format!("The build is disabled ({reason})")));
+    }
+
+    // apks of earlier builds must not be mistaken for the result of this one
+    let apk_name = format!("{package_name}_{version_code}.apk");
+    for stale_apk in [
+      self.unsigned_path()?.join(&apk_name),
+      self.path.join("tmp").join(&apk_name),
+    ] {
+      if stale_apk.is_file() {
+        debug!("Removing {stale_apk:?} of an earlier build");
+        fs::remove_file(stale_apk)?;
+      }
+    }
+
+    let log_path = self.build_log_path(package_name, version_code);
+    fs::create_dir_all(self.logs_path())?;
+    let mut log = File::create(&log_path)?;
+
+    let result = self.run_build_backend(package_name, version_code, &mut log);
+    let apk_path = result.map_err(fail).and_then(|_| {
+      self
+        .move_built_apk(&apk_name)?
+        .ok_or(fail("No apk has been produced".to_owned()))
+    });
+
+    self.emit(RepositoryEvent::BuildFinished {
+      package: package_name.to_owned(),
+      version_code,
+      success: apk_path.is_ok(),
+    });
+
+    let apk_path = apk_path?;
+    info!("Built {apk_path:?}");
+
+    Ok(BuildReport {
+      package: package_name.to_owned(),
+      version_code,
+      apk_path,
+      log_path,
+    })
+  }
+
+  /// Runs the [BuildBackend] while streaming its output to the log and the observers
+  ///
+  /// Returns the reason if the build failed
+  fn run_build_backend(
+    &self,
+    package_name: &str,
+    version_code: u64,
+    log: &mut File,
+  ) -> std::result::Result<(), String> {
+    let backend = &self.build_backend;
+    let command = format!("{} {}", backend.program, backend.args.join(" "));
+    debug!("Running build backend: \"{command}\"");
+
+    let mut process = Command::new(&backend.program)
+      .args(&backend.args)
+      .arg(format!("{package_name}:{version_code}"))
+      .current_dir(&self.path)
+      .stdin(Stdio::null())
+      .stdout(Stdio::piped())
+      .stderr(Stdio::piped())
+      .spawn()
+      .map_err(|err| format!("Could not run \"{}\": {err}", command.trim()))?;
+
+    let (sender, receiver) = mpsc::channel();
+    let stdout = process
+      .stdout
+      .take()
+      .map(|stdout| read_lines(stdout, sender.clone()));
+    let stderr = process
+      .stderr
+      .take()
+      .map(|stderr| read_lines(stderr, sender));
+
+    let mut outcome = BuildOutcome::new();
+    for line in receiver {
+      if let Err(err) = writeln!(log, "{line}") {
+        warn!("Could not write build log: {err}");
+      }
+      outcome.parse(&line);
+      self.emit(RepositoryEvent::BuildLog {
+        package: package_name.to_owned(),
+        version_code,
+        line,
+      });
+    }
+    stdout.into_iter().chain(stderr).for_each(|reader| {
+      let _ = reader.join();
+    });
+
+    let status = process
+      .wait()
+      .map_err(|err| format!("Could not wait for \"{}\": {err}", command.trim()))?;
+
+    if let Some(error) = outcome.error {
+      Err(error)
+    } else if !status.success() {
+      Err(format!("The build backend failed with {status}"))
+    } else if outcome.failed {
+      Err("The build backend reported a failure".to_owned())
+    } else {
+      Ok(())
+    }
+  }
+
+  /// Moves the apk produced by `fdroid build --test` into the unsigned directory
+  ///
+  /// Returns [None] if the backend did not produce an apk
+  fn move_built_apk(&self, apk_name: &str) -> Result<Option<PathBuf>> {
+    let apk_path = self.unsigned_path()?.join(apk_name);
+
+    if apk_path.is_file() {
+      return Ok(Some(apk_path));
+    }
+
+    let tmp_path = self.path.join("tmp").join(apk_name);
+    if !tmp_path.is_file() {
+      return Ok(None);
+    }
+
+    debug!("Moving {tmp_path:?} to {apk_path:?}");
+    if fs::rename(&tmp_path, &apk_path).is_err() {
+      // e.g. tmp/ is on another file system
+      fs::copy(&tmp_path, &apk_path)?;
+      fs::remove_file(&tmp_path)?;
+    }
+
+    Ok(Some(apk_path))
+  }
+}
+
+/// Failures reported in the output of the [BuildBackend]
+struct BuildOutcome {
+  /// the reason of `Could not build app <package>: <reason>`
+  error: Option<String>,
+  /// true if `<n> builds failed` with n > 0 has been printed
+  failed: bool,
+  error_regex: Regex,
+  failed_regex: Regex,
+}
+
+impl BuildOutcome {
+  fn new() -> Self {
+    Self {
+      error: None,
+      failed: false,
+      error_regex: Regex::new(r"Could not build app [^\s:]+:\s*(.+)$").expect("invalid regex"),
+      failed_regex: Regex::new(r"\b(\d+) builds? failed").expect("invalid regex"),
+    }
+  }
+
+  fn parse(&mut self, line: &str) {
+    if let Some(captures) = self.error_regex.captures(line) {
+      self.error = Some(captures[1].trim().to_owned());
+    }
+    if let Some(captures) = self.failed_regex.captures(line) {
+      self.failed |= captures[1].parse::<u64>().is_ok_and(|count| count > 0);
+    }
+  }
+}
+
+/// Sends every line of `source` to `sender` on a new thread
+fn read_lines(
+  source: impl Read + Send + 'static,
+  sender: mpsc::Sender<String>,
+) -> thread::JoinHandle<()> {
+  thread::spawn(move || {
+    for line in BufReader::new(source)
+      .split(b'\n')
+      .map_while(|line| line.ok())
+    {
+      let line = String::from_utf8_lossy(&line)
+        .trim_end_matches('\r')
+        .to_owned();
+      if sender.send(line).is_err() {
+        break;
+      }
+    }
+  })
+}
@@ -0,0 +1,373 @@
+//! Persistent cache of parsed files, similar to `tmp/apkcache` of fdroidserver
+//!
+//! Every entry is keyed by the path of the source file and stores its modification time,
+//! size and sha256 hash. An entry is reused if the modification time and size are unchanged,
+//! or if the file has been touched but its hash is the same.
+//! Entries of files which don't exist anymore are removed when the cache is saved.
+//! Values which have been read or computed are also kept in memory, so further calls only
+//! compare the modification time and size.
+//!
+//! The cache is stored in [Repository::cache_path] and shared by all clones of a [Repository]
+//! and all processes using the same repository. Writes hold a lock file and merge the entries
+//! of other processes, which have been written in the meantime.
+
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{debug, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::aapt::get_apk_info;
+use crate::error::{Error, Result};
+use crate::integrity::file_sha256;
+
+use super::Repository;
+
+/// Name of the cache file inside of `tmp/`
+pub(crate) const CACHE_FILE: &str = "fdroid-rs-cache.json";
+
+/// Changes whenever the format of a cached value changes, older caches are discarded
+const CACHE_VERSION: u32 = 1;
+
+/// How long a write waits for the lock of another process before it is skipped
+const LOCK_TIMEOUT: Duration = Duration::from_secs(2);
+/// Lock files older than this have been left behind by a crashed process
+const STALE_LOCK_AGE: Duration = Duration::from_secs(30);
+
+/// The content of the cache file
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CacheFile {
+  version: u32,
+  /// entries by `<kind>:<path>`
+  entries: BTreeMap<String, CacheEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct CacheEntry {
+  path: PathBuf,
+  /// modification time in nanoseconds since the unix epoch
+  mtime: u128,
+  size: u64,
+  sha256: String,
+  value: serde_json::Value,
+}
+
+#[derive(Default)]
+struct CacheState {
+  /// modification time of the cache file when it has been read
+  loaded_mtime: Option<SystemTime>,
+  file: CacheFile,
+}
+
+/// A value which has already been deserialized (or computed) by this process
+struct MemoryEntry {
+  path: PathBuf,
+  mtime: u128,
+  size: u64,
+  value: Arc<dyn Any + Send + Sync>,
+}
+
+/// Handle to the cache of a [Repository]
+#[derive(Clone)]
+pub(crate) struct Cache {
+  path: PathBuf,
+  state: Arc<Mutex<CacheState>>,
+  /// values by `<kind>:<path>`, so hits don't have to deserialize the json again
+  memory: Arc<Mutex<HashMap<String, MemoryEntry>>>,
+}
+
+impl fmt::Debug for Cache {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Cache({:?})", self.path)
+  }
+}
+
+impl Cache {
+  pub(crate) fn new(path: PathBuf) -> Self {
+    Self {
+      path,
+      state: Arc::default(),
+      memory: Arc::default(),
+    }
+  }
+
+  /// Returns the cached value for a file or computes and stores it
+  ///
+  /// `kind` separates different values of the same file. Errors of `compute` are not cached.
+  /// No lock is held while `compute` runs, so other threads can use the cache meanwhile.
+  pub(crate) fn get_or_insert<T>(
+    &self,
+    kind: &str,
+    file_path: &Path,
+    compute: impl FnOnce() -> Result<T>,
+  ) -> Result<T>
+  where
+    T: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+  {
+    let metadata = file_path.metadata()?;
+    let mtime = nanos(metadata.modified()?);
+    let size = metadata.len();
+    let key = format!("{kind}:{}", file_path.display());
+
+    if let Some(value) = self.memorized::<T>(&key, mtime, size) {
+      debug!("Using cached {kind} of {file_path:?} from memory");
+      return Ok(value);
+    }
+
+    let cached = {
+      let mut state = self.lock_state();
+      self.reload(&mut state);
+      state.file.entries.get(&key).cloned()
+    };
+
+    if let Some(entry) = &cached {
+      if entry.mtime == mtime && entry.size == size {
+        if let Ok(value) = serde_json::from_value::<T>(entry.value.clone()) {
+          debug!("Using cached {kind} of {file_path:?}");
+          self.memorize(key, file_path, mtime, size, &value);
+          return Ok(value);
+        }
+      }
+    }
+
+    let sha256 = file_sha256(&file_path.to_path_buf())?;
+
+    // the file has been touched, but its content did not change
+    if let Some(mut entry) = cached.filter(|entry| entry.sha256 == sha256 && entry.size == size) {
+      if let Ok(value) = serde_json::from_value::<T>(entry.value.clone()) {
+        debug!("Using cached {kind} of unchanged {file_path:?}");
+        entry.mtime = mtime;
+        self.store(key.clone(), entry);
+        self.memorize(key, file_path, mtime, size, &value);
+        return Ok(value);
+      }
+    }
+
+    let value = compute()?;
+
+    match serde_json::to_value(&value) {
+      Ok(json) => self.store(
+        key.clone(),
+        CacheEntry {
+          path: file_path.to_path_buf(),
+          mtime,
+          size,
+          sha256,
+          value: json,
+        },
+      ),
+      Err(err) => warn!("Could not cache {kind} of {file_path:?}: {err}"),
+    }
+    self.memorize(key, file_path, mtime, size, &value);
+
+    Ok(value)
+  }
+
+  /// Removes all entries
+  pub(crate) fn clear(&self) -> Result<()> {
+    let mut state = self.lock_state();
+    *state = CacheState::default();
+    self.lock_memory().clear();
+
+    if self.path.exists() {
+      fs::remove_file(&self.path)?;
+    }
+
+    Ok(())
+  }
+
+  fn lock_state(&self) -> MutexGuard<'_, CacheState> {
+    self.state.lock().unwrap_or_else(|err| err.into_inner())
+  }
+
+  fn lock_memory(&self) -> MutexGuard<'_, HashMap<String, MemoryEntry>> {
+    self.memory.lock().unwrap_or_else(|err| err.into_inner())
+  }
+
+  /// Returns the value of this process if the file has not been changed since
+  fn memorized<T: Clone + 'static>(&self, key: &str, mtime: u128, size: u64) -> Option<T> {
+    self
+      .lock_memory()
+      .get(key)
+      .filter(|entry| entry.mtime == mtime && entry.size == size)
+      .and_then(|entry| entry.value.downcast_ref::<T>().cloned())
+  }
+
+  fn memorize<T: Clone + Send + Sync + 'static>(
+    &self,
+    key: String,
+    path: &Path,
+    mtime: u128,
+    size: u64,
+    value: &T,
+  ) {
+    self.lock_memory().insert(
+      key,
+      MemoryEntry {
+        path: path.to_path_buf(),
+        mtime,
+        size,
+        value: Arc::new(value.clone()),
+      },
+    );
+  }
+
+  /// Reads the cache file again if another process changed it
+  fn reload(&self, state: &mut CacheState) {
+    let disk_mtime = self
+      .path
+      .metadata()
+      .and_then(|metadata| metadata.modified())
+      .ok();
+
+    if disk_mtime.is_none() || disk_mtime == state.loaded_mtime {
+      return;
+    }
+
+    self.read(state, disk_mtime);
+  }
+
+  fn read(&self, state: &mut CacheState, disk_mtime: Option<SystemTime>) {
+    state.file = fs::read_to_string(&self.path)
+      .ok()
+      .and_then(|content| serde_json::from_str::<CacheFile>(&content).ok())
+      .filter(|file| file.version == CACHE_VERSION)
+      .unwrap_or_default();
+    state.loaded_mtime = disk_mtime;
+  }
+
+  /// Adds an entry and writes the cache file, failures only disable the cache
+  ///
+  /// The file is read again while holding the lock, so entries written by other processes
+  /// since the last read are kept.
+  fn store(&self, key: String, entry: CacheEntry) {
+    let mut state = self.lock_state();
+    let Some(_lock) = CacheLock::acquire(&self.path) else {
+      warn!("Could not lock cache {:?}, skipping write", self.path);
+      return;
+    };
+
+    // the modification time might not change for fast writes, always read the file
+    let disk_mtime = self
+      .path
+      .metadata()
+      .and_then(|metadata| metadata.modified())
+      .ok();
+    self.read(&mut state, disk_mtime);
+
+    state.file.version = CACHE_VERSION;
+    state.file.entries.insert(key, entry);
+    state.file.entries.retain(|_, entry| entry.path.is_file());
+    self.lock_memory().retain(|_, entry| entry.path.is_file());
+
+    match write_cache_file(&self.path, &state.file) {
+      Ok(mtime) => state.loaded_mtime = Some(mtime),
+      Err(err) => warn!("Could not write cache {:?}: {err}", self.path),
+    }
+  }
+}
+
+/// Lock file next to the cache file, which is removed when dropped
+struct CacheLock(PathBuf);
+
+impl CacheLock {
+  /// Waits up to [LOCK_TIMEOUT] for the lock, returns [None] if it is still held
+  fn acquire(cache_path: &Path) -> Option<Self> {
+    let lock_path = cache_path.with_extension("lock");
+    if let Some(parent) = lock_path.parent() {
+      fs::create_dir_all(parent).ok()?;
+    }
+
+    let started = SystemTime::now();
+    loop {
+      match fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock_path)
+      {
+        Ok(_) => return Some(Self(lock_path)),
+        Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+          let age = lock_path
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok());
+          if age.is_some_and(|age| age > STALE_LOCK_AGE) {
+            debug!("Removing stale cache lock {lock_path:?}");
+            let _ = fs::remove_file(&lock_path);
+            continue;
+          }
+        }
+        Err(err) => {
+          debug!("Could not create cache lock {lock_path:?}: {err}");
+          return None;
+        }
+      }
+
+      if started.elapsed().unwrap_or_default() > LOCK_TIMEOUT {
+        return None;
+      }
+      thread::sleep(Duration::from_millis(10));
+    }
+  }
+}
+
+impl Drop for CacheLock {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.0);
+  }
+}
+
+impl Repository {
+  /// Returns the output of `aapt dump badging` for an apk, cached across calls and processes
+  ///
+  /// # Error
+  /// Returns an error if the file does not exist or can't be parsed
+  pub fn apk_info(&self, apk_path: &PathBuf) -> Result<String> {
+    if !apk_path.is_file() {
+      // same error as without the cache
+      return get_apk_info(apk_path);
+    }
+
+    self
+      .cache
+      .get_or_insert("apk_info", apk_path, || get_apk_info(apk_path))
+  }
+
+  /// Deletes the cache of parsed index files and apk info
+  ///
+  /// It never has to be called manually, as changed files are detected automatically.
+  pub fn clear_cache(&self) -> Result<()> {
+    self.cache.clear()
+  }
+}
+
+/// Writes the cache file atomically (so other processes never read a partial file) and
+/// returns its new modification time
+fn write_cache_file(path: &Path, file: &CacheFile) -> Result<SystemTime> {
+  if let Some(parent) = path.parent() {
+    fs::create_dir_all(parent)?;
+  }
+
+  let content = serde_json::to_string(file).map_err(|err| Error::JsonConvert(err.to_string()))?;
+
+  let temp_path = path.with_extension(format!("{}.tmp", std::process::id()));
+  fs::write(&temp_path, content)?;
+  fs::rename(&temp_path, path)?;
+
+  Ok(path.metadata()?.modified()?)
+}
+
+fn nanos(time: SystemTime) -> u128 {
+  time
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_nanos())
+    .unwrap_or_default()
+}
@@ -0,0 +1,690 @@
+//! Extension of Repository used to check apps for new versions, similar to `fdroid checkupdates`
+//!
+//! Supports [UpdateCheckMode::Tags] and [UpdateCheckMode::RepoManifest] for git repositories
+//! and [UpdateCheckMode::Http].
+//! If `Repo` of the metadata is a local directory, it is used directly. Otherwise, it is cloned
+//! into [Repository::checkupdates_path] and fetched again on further checks.
+//!
+//! The files are read with `git show`, so no working tree is ever changed.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use log::{debug, info};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::metadata::{AppMetadata, AutoUpdateMode, Builds, RepoType, UpdateCheckMode};
+
+use super::Repository;
+
+/// A version found by [Repository::check_updates]
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct UpdateCheck {
+  pub version_name: String,
+  /// highest version code after applying `VercodeOperation`
+  pub version_code: u64,
+  /// version code found in the source
+  pub upstream_version_code: u64,
+  /// the tag (with [UpdateCheckMode::Tags]) or commit hash the version has been found in,
+  /// [None] with [UpdateCheckMode::Http]
+  pub commit: Option<String>,
+  /// true if `CurrentVersion` and `CurrentVersionCode` have been changed
+  pub updated: bool,
+}
+
+/// Parsed `UpdateCheckData` of the form
+/// `<vercode-location>|<regex-vercode>|<versionName-location>|<regex-versionName>`
+///
+/// The locations are urls with [UpdateCheckMode::Http] and paths inside of the source
+/// repository with [UpdateCheckMode::Tags].
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct UpdateCheckData {
+  /// empty to use the name of the tag
+  pub code_location: String,
+  /// the first group is the version code
+  pub code_regex: String,
+  /// `.` to use the document of the version code, empty to use the name of the tag
+  pub name_location: String,
+  /// the first group is the version name
+  pub name_regex: String,
+}
+
+impl FromStr for UpdateCheckData {
+  type Err = Error;
+
+  fn from_str(data: &str) -> Result<Self> {
+    let [code_location, code_regex, name_location, name_regex]: [&str; 4] = data
+      .split('|')
+      .collect::<Vec<_>>()
+      .try_into()
+      .map_err(|_| {
+        Error::UpdateCheck(format!("UpdateCheckData \"{data}\" does not have 4 parts"))
+      })?;
+
+    Ok(Self {
+      code_location: code_location.to_owned(),
+      code_regex: code_regex.to_owned(),
+      name_location: name_location.to_owned(),
+      name_regex: name_regex.to_owned(),
+    })
+  }
+}
+
+/// Version found by [UpdateCheckData::check_http]
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct HttpVersion {
+  pub version_code: u64,
+  /// [None] if the version name location is empty
+  pub version_name: Option<String>,
+}
+
+impl UpdateCheckData {
+  /// Fetches the documents and applies the regexes, like [UpdateCheckMode::Http]
+  ///
+  /// # Error
+  /// Returns an error if a url does not use https, can't be fetched or a regex does not match
+  pub fn check_http(&self, client: &dyn HttpClient) -> Result<HttpVersion> {
+    let fetch = |url: &str| {
+      if !url.starts_with("https://") {
+        return Err(Error::UpdateCheck(format!(
+          "UpdateCheckData has to use https urls, got \"{url}\""
+        )));
+      }
+      debug!("Fetching {url}");
+      client.get(url)
+    };
+    let no_match = |what: &str| Error::UpdateCheck(format!("No match for the {what}"));
+
+    if self.code_location.is_empty() {
+      return Err(Error::UpdateCheck(
+        "UpdateCheckData has no version code url".to_owned(),
+      ));
+    }
+
+    let code_document = fetch(&self.code_location)?;
+    let version_code = capture(&self.code_regex, &code_document)?
+      .and_then(|version_code| version_code.trim().parse().ok())
+      .ok_or(no_match("version code"))?;
+
+    let version_name = match self.name_location.as_str() {
+      "" => None,
+      location => {
+        let name_document = match location {
+          "." => code_document,
+          url => fetch(url)?,
+        };
+        Some(capture(&self.name_regex, &name_document)?.ok_or(no_match("version name"))?)
+      }
+    };
+
+    Ok(HttpVersion {
+      version_code,
+      version_name,
+    })
+  }
+}
+
+/// Fetches the documents of [UpdateCheckMode::Http]
+///
+/// Closures taking a url implement this trait as well, which is useful in tests:
+/// ```
+/// # use fdroid::{HttpClient, UpdateCheckData};
+/// let client = |_url: &str| Ok("versionCode: 12".to_owned());
+/// let data: UpdateCheckData = "https://example.org|versionCode: (\\d+)||".parse().unwrap();
+/// assert_eq!(data.check_http(&client).unwrap().version_code, 12);
+/// ```
+pub trait HttpClient {
+  /// Returns the body of a `GET` request
+  fn get(&self, url: &str) -> Result<String>;
+}
+
+impl<F> HttpClient for F
+where
+  F: Fn(&str) -> Result<String>,
+{
+  fn get(&self, url: &str) -> Result<String> {
+    self(url)
+  }
+}
+
+/// The default [HttpClient], using ureq
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UreqClient;
+
+impl HttpClient for UreqClient {
+  fn get(&self, url: &str) -> Result<String> {
+    ureq::get(url)
+      .call()
+      .map_err(|err| Error::Http(format!("{url}: {err}")))?
+      .into_string()
+      .map_err(|err| Error::Http(format!("{url}: {err}")))
+  }
+}
+
+impl Repository {
+  /// Checks the source of an app for a new version
+  ///
+  /// If the found version code is higher than `CurrentVersionCode`, `CurrentVersion` and
+  /// `CurrentVersionCode` are updated. Versions whose name matches `UpdateCheckIgnore` are
+  /// skipped. Returns [None] if the `UpdateCheckMode` is unset, [UpdateCheckMode::None]
+  /// or [UpdateCheckMode::Static].
+  ///
+  /// Use [Repository::auto_update] afterwards to add a build for the new version.
+  ///
+  /// # Error
+  /// Returns an error if
+  /// - the metadata can't be read or written
+  /// - the mode is not supported or the repository is not a git repository
+  /// - the repository can't be cloned or fetched
+  /// - no version could be found
+  pub fn check_updates(&self, package_name: &str) -> Result<Option<UpdateCheck>> {
+    self.check_updates_with(package_name, &UreqClient)
+  }
+
+  /// Same as [Repository::check_updates], but fetches the documents of
+  /// [UpdateCheckMode::Http] with `client`
+  pub fn check_updates_with(
+    &self,
+    package_name: &str,
+    client: &dyn HttpClient,
+  ) -> Result<Option<UpdateCheck>> {
+    let mut metadata = self.metadata(package_name)?;
+
+    let Some(found) =
+      self
+        .find_update(package_name, &metadata, client)
+        .map_err(|err| match err {
+          Error::UpdateCheck(reason) => Error::UpdateCheck(format!("{package_name}: {reason}")),
+          err => err,
+        })?
+    else {
+      return Ok(None);
+    };
+
+    let current_version_code = metadata
+      .CurrentVersionCode
+      .as_deref()
+      .and_then(|version_code| version_code.parse::<u64>().ok());
+
+    let updated = current_version_code.is_none_or(|current| found.version_code > current);
+
+    if updated {
+      info!(
+        "Found new version {} ({}) of {package_name}",
+        found.version_name, found.version_code
+      );
+      metadata.CurrentVersion = Some(found.version_name.clone());
+      metadata.CurrentVersionCode = Some(found.version_code.to_string());
+      self.set_metadata(package_name, &metadata)?;
+    } else {
+      debug!("{package_name} is up to date");
+    }
+
+    Ok(Some(UpdateCheck { updated, ..found }))
+  }
+
+  /// Appends builds for a version found by [Repository::check_updates] if `AutoUpdateMode`
+  /// is [AutoUpdateMode::Version]
+  ///
+  /// One build is added for every version code of
+  /// [AppMetadata::expected_version_codes] (e.g. one per ABI). Every new build is a copy of
+  /// the corresponding one of the last builds with a new `versionName`, `versionCode` and
+  /// `commit`. The commit is created from the pattern of [AutoUpdateMode::Version]
+  /// (`%v` is replaced with the version name and `%c` with the upstream version code, a
+  /// leading `+<suffix>` is appended to the version name) or is the found tag or commit.
+  ///
+  /// Builds are only added for version codes which are higher than all existing builds and,
+  /// unless [UpdateCheck::updated] is set, higher than `CurrentVersionCode`.
+  ///
+  /// Returns the new builds, which is empty if auto-updating is disabled, the version is not
+  /// newer or all builds already exist.
+  ///
+  /// # Error
+  /// Returns an error if the metadata can't be read or written, `VercodeOperation` is invalid
+  /// or no commit can be determined
+  pub fn auto_update(&self, package_name: &str, update_check: &UpdateCheck) -> Result<Vec<Builds>> {
+    let mut metadata = self.metadata(package_name)?;
+    let failed = |reason: String| Error::UpdateCheck(format!("{package_name}: {reason}"));
+
+    let pattern = match &metadata.AutoUpdateMode {
+      Some(AutoUpdateMode::Version(pattern)) => pattern.clone(),
+      _ => return Ok(vec![]),
+    };
+
+    let version_codes = metadata
+      .expected_version_codes(update_check.upstream_version_code)
+      .map_err(|err| failed(format!("VercodeOperation: {err}")))?;
+
+    // never add builds for a version which is older than the existing ones
+    let parse = |version_code: Option<&String>| version_code.and_then(|code| code.parse().ok());
+    let highest_build = metadata
+      .Builds
+      .iter()
+      .flatten()
+      .filter_map(|build| parse(build.versionCode.as_ref()))
+      .max();
+    let current_version_code = parse(metadata.CurrentVersionCode.as_ref());
+    let is_newer = highest_build.is_none_or(|highest| update_check.version_code > highest)
+      && (update_check.updated
+        || current_version_code.is_none_or(|current| update_check.version_code > current));
+    if !is_newer {
+      debug!(
+        "{package_name}: {} is not newer than the existing builds",
+        update_check.version_code
+      );
+      return Ok(vec![]);
+    }
+
+    let upstream_version_code = update_check.upstream_version_code.to_string();
+    let builds = metadata.Builds.get_or_insert_with(Vec::new);
+
+    // the last builds are the templates, in the same order as the version codes
+    let templates: Vec<Builds> = builds
+      .iter()
+      .skip(builds.len().saturating_sub(version_codes.len()))
+      .cloned()
+      .collect();
+
+    let (suffix, commit) = match pattern.as_deref() {
+      Some(pattern) => {
+        let (suffix, pattern) = match pattern.strip_prefix('+') {
+          Some(rest) => rest.split_once(' ').unwrap_or((rest, "%v")),
+          None => ("", pattern),
+        };
+        let commit = pattern
+          .replace("%v", &update_check.version_name)
+          .replace("%c", &upstream_version_code);
+        (suffix, commit)
+      }
+      None => (
+        "",
+        update_check.commit.clone().ok_or(failed(
+          "AutoUpdateMode Version needs a pattern without a tag or commit".to_owned(),
+        ))?,
+      ),
+    };
+
+    let mut added = vec![];
+
+    for (index, version_code) in version_codes.into_iter().enumerate() {
+      if highest_build.is_some_and(|highest| version_code <= highest) {
+        debug!("{package_name} already has a build for {version_code} or a newer version");
+        continue;
+      }
+      let version_code = version_code.to_string();
+
+      let build = Builds {
+        versionName: Some(format!("{}{suffix}", update_check.version_name)),
+        versionCode: Some(version_code),
+        commit: Some(commit.clone()),
+        disable: None,
+        ..templates
+          .get(index)
+          .or(templates.last())
+          .cloned()
+          .unwrap_or_default()
+      };
+
+      info!(
+        "Adding build {} of {package_name}",
+        build.versionCode.as_deref().unwrap_or_default()
+      );
+      builds.push(build.clone());
+      added.push(build);
+    }
+
+    if !added.is_empty() {
+      self.set_metadata(package_name, &metadata)?;
+    }
+
+    Ok(added)
+  }
+
+  /// Finds the newest version (after applying `VercodeOperation`) without changing anything
+  fn find_update(
+    &self,
+    package_name: &str,
+    metadata: &AppMetadata,
+    client: &dyn HttpClient,
+  ) -> Result<Option<UpdateCheck>> {
+    let failed = |reason: String| Error::UpdateCheck(reason);
+
+    let mode = match &metadata.UpdateCheckMode {
+      None | Some(UpdateCheckMode::None) | Some(UpdateCheckMode::Static) => return Ok(None),
+      Some(mode) => mode.clone(),
+    };
+
+    let ignore = metadata
+      .UpdateCheckIgnore
+      .as_deref()
+      .map(|ignore| Regex::new(ignore).map_err(|err| failed(format!("UpdateCheckIgnore: {err}"))))
+      .transpose()?;
+    let is_ignored = |version: &UpdateCheck| {
+      ignore
+        .as_ref()
+        .is_some_and(|ignore| ignore.is_match(&version.version_name))
+    };
+
+    let data = metadata
+      .UpdateCheckData
+      .as_deref()
+      .map(UpdateCheckData::from_str)
+      .transpose()?;
+
+    let found = match &mode {
+      UpdateCheckMode::Http => {
+        let data = data.ok_or(failed(
+          "UpdateCheckMode HTTP requires UpdateCheckData".to_owned(),
+        ))?;
+        let version = data.check_http(client)?;
+
+        Some(UpdateCheck {
+          // fall back to the version code
+          version_name: version
+            .version_name
+            .unwrap_or(version.version_code.to_string()),
+          version_code: version.version_code,
+          upstream_version_code: version.version_code,
+          commit: None,
+          updated: false,
+        })
+        .filter(|version| !is_ignored(version))
+      }
+      UpdateCheckMode::Tags(pattern) => {
+        let source = self.git_source(package_name, metadata)?;
+        let pattern = pattern
+          .as_deref()
+          .map(|pattern| Regex::new(pattern).map_err(|err| failed(format!("Tags: {err}"))))
+          .transpose()?;
+
+        let mut newest: Option<UpdateCheck> = None;
+
+        for tag in source.tags()? {
+          // like python's re.match, the pattern has to match at the start
+          if pattern
+            .as_ref()
+            .is_some_and(|pattern| pattern.find(&tag).is_none_or(|found| found.start() != 0))
+          {
+            continue;
+          }
+
+          let Some(version) = find_version(&source, &tag, &tag, metadata, data.as_ref())? else {
+            debug!("No version information in tag {tag}");
+            continue;
+          };
+
+          if is_ignored(&version) {
+            debug!("Ignoring {} of tag {tag}", version.version_name);
+            continue;
+          }
+
+          // tags are sorted from new to old, so the newest tag wins on equal version codes
+          if newest
+            .as_ref()
+            .is_none_or(|newest| version.version_code > newest.version_code)
+          {
+            newest = Some(version);
+          }
+        }
+
+        newest
+      }
+      UpdateCheckMode::RepoManifest(branch) => {
+        let source = self.git_source(package_name, metadata)?;
+        let rev = branch.as_deref().unwrap_or("HEAD");
+        let commit = source.git(&["rev-parse", &format!("{rev}^{{commit}}")])?;
+
+        find_version(&source, rev, commit.trim(), metadata, None)?
+          .filter(|version| !is_ignored(version))
+      }
+      _ => {
+        return Err(failed(format!(
+          "UpdateCheckMode {} is not supported",
+          mode.name()
+        )))
+      }
+    };
+
+    let mut found = found.ok_or(failed("no version information found".to_owned()))?;
+
+    found.version_code = metadata
+      .expected_version_codes(found.upstream_version_code)
+      .map_err(|err| failed(format!("VercodeOperation: {err}")))?
+      .last()
+      .copied()
+      .ok_or(failed(format!(
+        "VercodeOperation has no valid result for {}",
+        found.upstream_version_code
+      )))?;
+
+    Ok(Some(found))
+  }
+
+  /// Opens the git repository of an app
+  fn git_source(&self, package_name: &str, metadata: &AppMetadata) -> Result<GitSource> {
+    if metadata
+      .RepoType
+      .as_ref()
+      .is_some_and(|repo_type| repo_type != &RepoType::Git)
+    {
+      return Err(Error::UpdateCheck(
+        "only git repositories are supported".to_owned(),
+      ));
+    }
+
+    let url = metadata
+      .Repo
+      .as_deref()
+      .ok_or(Error::UpdateCheck("Repo is not set".to_owned()))?;
+
+    self.open_source(package_name, url)
+  }
+
+  /// Opens a local git repository or clones/fetches a remote one
+  fn open_source(&self, package_name: &str, url: &str) -> Result<GitSource> {
+    let local = Path::new(url);
+    if local.is_dir() {
+      return Ok(GitSource {
+        dir: local.to_path_buf(),
+      });
+    }
+
+    let source = GitSource {
+      dir: self.checkupdates_path().join(package_name),
+    };
+
+    if source.dir.is_dir() {
+      info!("Fetching {url}");
+      source.git(&[
+        "fetch",
+        "--quiet",
+        "--prune",
+        "--tags",
+        "origin",
+        "+refs/heads/*:refs/heads/*",
+      ])?;
+    } else {
+      info!("Cloning {url}");
+      std::fs::create_dir_all(self.checkupdates_path())?;
+      run_git(
+        &self.checkupdates_path(),
+        // `--` prevents a `Repo` starting with `-` from being read as an option
+        &["clone", "--quiet", "--bare", "--", url, package_name],
+      )?;
+    }
+
+    Ok(source)
+  }
+}
+
+/// A git repository containing the source code of an app
+struct GitSource {
+  dir: PathBuf,
+}
+
+impl GitSource {
+  fn git(&self, args: &[&str]) -> Result<String> {
+    run_git(&self.dir, args)
+  }
+
+  /// Returns all tags, the newest first
+  fn tags(&self) -> Result<Vec<String>> {
+    Ok(
+      self
+        .git(&[
+          "for-each-ref",
+          "--sort=-creatordate",
+          "--format=%(refname:short)",
+          "refs/tags",
+        ])?
+        .lines()
+        .map(str::to_owned)
+        .collect(),
+    )
+  }
+
+  /// Returns the content of a file at a revision, [None] if it does not exist
+  fn show(&self, rev: &str, path: &str) -> Option<String> {
+    self.git(&["show", &format!("{rev}:{path}")]).ok()
+  }
+}
+
+/// Runs git inside of `dir` and returns stdout
+pub(super) fn run_git(dir: &Path, args: &[&str]) -> Result<String> {
+  let command_string = format!("git {}", args.join(" "));
+  debug!("Running {command_string} in {dir:?}");
+
+  let output = Command::new("git")
+    .args(args)
+    .current_dir(dir)
+    // never wait for credentials
+    .env("GIT_TERMINAL_PROMPT", "0")
+    .output()
+    .map_err(|_| Error::Run(command_string.clone()))?;
+
+  if !output.status.success() {
+    debug!(
+      "git failed: {}",
+      String::from_utf8_lossy(&output.stderr).trim()
+    );
+    return Err(Error::Run(command_string));
+  }
+
+  Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Looks for the version at `rev`
+///
+/// Uses `data` if it is set, otherwise the gradle files and `AndroidManifest.xml` inside of
+/// the `subdir` of the last build (or the root and `app/`).
+fn find_version(
+  source: &GitSource,
+  rev: &str,
+  commit: &str,
+  metadata: &AppMetadata,
+  data: Option<&UpdateCheckData>,
+) -> Result<Option<UpdateCheck>> {
+  let found = |version_code: Option<u64>, version_name: Option<String>| {
+    version_code.map(|version_code| UpdateCheck {
+      // fall back to the name of the tag or branch
+      version_name: version_name.unwrap_or(rev.to_owned()),
+      version_code,
+      upstream_version_code: version_code,
+      commit: Some(commit.to_owned()),
+      updated: false,
+    })
+  };
+
+  if let Some(data) = data {
+    let code_content = match data.code_location.as_str() {
+      "" => Some(rev.to_owned()),
+      path => source.show(rev, path),
+    };
+    let name_content = match data.name_location.as_str() {
+      "" => Some(rev.to_owned()),
+      "." => code_content.clone(),
+      path => source.show(rev, path),
+    };
+
+    let version_code = code_content
+      .map(|content| capture(&data.code_regex, &content))
+      .transpose()?
+      .flatten()
+      .and_then(|version_code| version_code.trim().parse().ok());
+    let version_name = name_content
+      .map(|content| match data.name_regex.as_str() {
+        "" => Ok(Some(content.trim().to_owned())),
+        regex => capture(regex, &content),
+      })
+      .transpose()?
+      .flatten();
+
+    return Ok(found(version_code, version_name));
+  }
+
+  let subdir = metadata
+    .Builds
+    .as_ref()
+    .and_then(|builds| builds.last())
+    .and_then(|build| build.subdir.as_deref())
+    .map(|subdir| subdir.trim_matches('/'))
+    .filter(|subdir| !subdir.is_empty() && *subdir != ".");
+  let directories = match subdir {
+    Some(subdir) => vec![format!("{subdir}/")],
+    None => vec![String::new(), "app/".to_owned()],
+  };
+
+  let mut version_code = None;
+  let mut version_name = None;
+
+  for directory in directories {
+    for (file, code_regex, name_regex) in [
+      ("build.gradle", GRADLE_VERSION_CODE, GRADLE_VERSION_NAME),
+      ("build.gradle.kts", GRADLE_VERSION_CODE, GRADLE_VERSION_NAME),
+      (
+        "src/main/AndroidManifest.xml",
+        MANIFEST_VERSION_CODE,
+        MANIFEST_VERSION_NAME,
+      ),
+      (
+        "AndroidManifest.xml",
+        MANIFEST_VERSION_CODE,
+        MANIFEST_VERSION_NAME,
+      ),
+    ] {
+      let Some(content) = source.show(rev, &format!("{directory}{file}")) else {
+        continue;
+      };
+
+      if version_code.is_none() {
+        version_code = capture(code_regex, &content)?.and_then(|code| code.parse().ok());
+      }
+      if version_name.is_none() {
+        version_name = capture(name_regex, &content)?;
+      }
+    }
+  }
+
+  Ok(found(version_code, version_name))
+}
+
+pub(super) const GRADLE_VERSION_CODE: &str = r"versionCode\s*=?\s*(\d+)";
+pub(super) const GRADLE_VERSION_NAME: &str = r#"versionName\s*=?\s*["']([^"']+)["']"#;
+pub(super) const MANIFEST_VERSION_CODE: &str = r#"android:versionCode\s*=\s*["'](\d+)["']"#;
+pub(super) const MANIFEST_VERSION_NAME: &str = r#"android:versionName\s*=\s*["']([^"']+)["']"#;
+
+/// Returns the first group (or the whole match) of `regex` in `content`
+pub(super) fn capture(regex: &str, content: &str) -> Result<Option<String>> {
+  let regex = Regex::new(regex)
+    .map_err(|err| Error::UpdateCheck(format!("invalid regex \"{regex}\": {err}")))?;
+
+  Ok(regex.captures(content).and_then(|captures| {
+    captures
+      .get(1)
+      .or(captures.get(0))
+      .map(|found| found.as_str().to_owned())
+  }))
+}
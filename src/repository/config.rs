@@ -7,14 +7,15 @@ use std::path::PathBuf;
 use crate::error::{Error, InvalidFile, Result};
 use serde::{Deserialize, Serialize};
 
-use super::Repository;
+use super::deploy::ServerWebRoot;
+use super::{Repository, RepositoryEvent, WebhookEndpoint};
 
 /// Actual Structure of the config.yml file
 #[derive(Serialize, Deserialize, Debug)]
-struct ConfigFile {
+pub(crate) struct ConfigFile {
   // immutable part
   sdk_path: String,
-  repo_keyalias: String,
+  pub(crate) repo_keyalias: String,
   keystore: String,
   keystorepass: String,
   keypass: String,
@@ -42,6 +43,25 @@ struct ConfigFile {
   archive_description: Option<String>,
   #[serde(skip_serializing_if = "Option::is_none")]
   archive_older: Option<u8>,
+  // deploy
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) serverwebroot: Option<ServerWebRoot>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) identity_file: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) awsbucket: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) awsaccesskeyid: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) awssecretkey: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) awsregion: Option<String>,
+  /// endpoint of an S3 compatible storage (e.g. MinIO), defaults to AWS
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) awsendpoint: Option<String>,
+  // notifications
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub(crate) webhooks: Option<Vec<WebhookEndpoint>>,
 }
 
 impl ConfigFile {
@@ -55,6 +75,14 @@ impl ConfigFile {
       keypass: self.keypass.clone(),
       keydname: self.keydname.clone(),
       apksigner: self.apksigner.clone(),
+      serverwebroot: self.serverwebroot.clone(),
+      identity_file: self.identity_file.clone(),
+      awsbucket: self.awsbucket.clone(),
+      awsaccesskeyid: self.awsaccesskeyid.clone(),
+      awssecretkey: self.awssecretkey.clone(),
+      awsregion: self.awsregion.clone(),
+      awsendpoint: self.awsendpoint.clone(),
+      webhooks: self.webhooks.clone(),
       repo_url: public.repo_url.clone(),
       repo_name: public.repo_name.clone(),
       repo_icon: public.repo_icon.clone(),
@@ -180,7 +208,7 @@ impl Repository {
   ///
   /// # Error
   /// Returns an error if the file can't be read or deserialized
-  fn get_config(&self) -> Result<ConfigFile> {
+  pub(crate) fn get_config(&self) -> Result<ConfigFile> {
     let yml_string = fs::read_to_string(self.config_path())?;
 
     serde_yaml::from_str::<ConfigFile>(&yml_string).map_err(Error::from)
@@ -194,6 +222,8 @@ impl Repository {
     // write to file
     fs::write(self.config_path(), yml_string)?;
 
+    self.emit(RepositoryEvent::ConfigChanged);
+
     // update repository
     self.update()
   }
@@ -0,0 +1,395 @@
+//! Extension of Repository used to deploy the repository to web servers
+//!
+//! The targets are read from `serverwebroot` in the config file, the same way `fdroid deploy` does.
+//! See [documentation](https://f-droid.org/en/docs/Setup_an_F-Droid_App_Repo/)
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::integrity::file_sha256;
+
+use super::s3::{sync_s3, Credentials, S3Client, DEFAULT_REGION};
+use super::Repository;
+
+/// Files which reference the other files of the repository and therefore have to be
+/// uploaded last
+const INDEX_FILES: [&str; 8] = [
+  "entry.jar",
+  "entry.json",
+  "index-v1.jar",
+  "index-v1.json",
+  "index-v2.json",
+  "index.jar",
+  "index.xml",
+  "index.html",
+];
+
+/// Value of `serverwebroot` inside of the config file
+///
+/// Can either be a single target or a list of targets.
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum ServerWebRoot {
+  Single(String),
+  Multiple(Vec<ServerWebRootEntry>),
+}
+
+/// A single entry of a `serverwebroot` list, either the target itself or a map with its `url`
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(untagged)]
+pub(crate) enum ServerWebRootEntry {
+  Url(String),
+  Map {
+    url: String,
+    /// only deploy the index files (not supported, all files are deployed)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    index_only: bool,
+  },
+}
+
+impl ServerWebRoot {
+  fn targets(&self) -> Vec<DeployTarget> {
+    match self {
+      Self::Single(target) => vec![DeployTarget::parse(target)],
+      Self::Multiple(entries) => entries.iter().map(ServerWebRootEntry::target).collect(),
+    }
+  }
+}
+
+impl ServerWebRootEntry {
+  fn target(&self) -> DeployTarget {
+    match self {
+      Self::Url(url) => DeployTarget::parse(url),
+      Self::Map { url, index_only } => {
+        if *index_only {
+          warn!("index_only of serverwebroot {url} is not supported, all files are deployed");
+        }
+        DeployTarget::parse(url)
+      }
+    }
+  }
+}
+
+/// A single location the repository is deployed to
+///
+/// Both `repo/` and `archive/` are synced into the target directory.
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub enum DeployTarget {
+  /// A directory on the local file system (e.g. a mounted web root)
+  Local(PathBuf),
+  /// An rsync destination in the form `[user@]host:/path`, synced over SSH
+  Rsync(String),
+  /// A bucket of an S3 compatible object storage, configured with the `aws*` keys
+  ///
+  /// The files are stored below `fdroid/` inside of the bucket.
+  S3 { endpoint: String, bucket: String },
+}
+
+impl DeployTarget {
+  /// Parses a single `serverwebroot` entry
+  ///
+  /// Entries containing a `:` before the first `/` are rsync destinations,
+  /// all other entries are local directories.
+  pub fn parse(target: &str) -> Self {
+    let target = target.trim_end_matches('/');
+
+    match target.find(':') {
+      Some(colon) if !target[..colon].contains('/') => Self::Rsync(target.to_owned()),
+      _ => Self::Local(PathBuf::from(target)),
+    }
+  }
+}
+
+/// Changes made to a [DeployTarget]
+///
+/// Paths are relative to the target directory, e.g. `repo/index-v1.jar`.
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct DeployReport {
+  /// new or changed files, in the order they have been uploaded
+  pub uploaded: Vec<PathBuf>,
+  /// files which do not exist in the repository anymore
+  pub deleted: Vec<PathBuf>,
+}
+
+impl Repository {
+  /// Returns the targets configured in `serverwebroot` and `awsbucket`
+  ///
+  /// If `awsendpoint` is not set, the AWS endpoint of `awsregion` is used.
+  ///
+  /// # Error
+  /// Returns an error if the config file can't be read
+  pub fn deploy_targets(&self) -> Result<Vec<DeployTarget>> {
+    let config_file = self.get_config()?;
+
+    let mut targets = config_file
+      .serverwebroot
+      .map(|serverwebroot| serverwebroot.targets())
+      .unwrap_or_default();
+
+    if let Some(bucket) = config_file.awsbucket {
+      let endpoint = config_file.awsendpoint.unwrap_or(format!(
+        "https://s3.{}.amazonaws.com",
+        config_file.awsregion.as_deref().unwrap_or(DEFAULT_REGION)
+      ));
+
+      targets.push(DeployTarget::S3 { endpoint, bucket });
+    }
+
+    Ok(targets)
+  }
+
+  /// Deploys the repository to all targets configured in `serverwebroot` and `awsbucket`
+  ///
+  /// # Error
+  /// Returns [Error::MissingConfig] if no target is configured or the error of the first
+  /// failing target
+  pub fn deploy(&self) -> Result<Vec<(DeployTarget, DeployReport)>> {
+    let targets = self.deploy_targets()?;
+
+    if targets.is_empty() {
+      return Err(Error::MissingConfig("serverwebroot".to_owned()));
+    }
+
+    targets
+      .into_iter()
+      .map(|target| {
+        let report = self.deploy_to(&target)?;
+        Ok((target, report))
+      })
+      .collect()
+  }
+
+  /// Syncs `repo/` and `archive/` to a single target
+  ///
+  /// Only files whose content changed are uploaded. The index files are uploaded after all
+  /// other files, so clients never see an index referencing missing apks.
+  /// Files which don't exist in the repository anymore are deleted last.
+  ///
+  /// # Error
+  /// Sends [RepositoryEvent::PublishFailed](super::RepositoryEvent::PublishFailed) to all
+  /// observers if the target can't be synced
+  pub fn deploy_to(&self, target: &DeployTarget) -> Result<DeployReport> {
+    info!("Deploying repository to {target:?}");
+
+    self
+      .sync_to(target)
+      .map_err(|err| self.publish_failed(Some(target), err))
+  }
+
+  fn sync_to(&self, target: &DeployTarget) -> Result<DeployReport> {
+    let mut report = DeployReport::default();
+
+    for source in [self.repo_path(), self.archive_path()] {
+      if !source.is_dir() {
+        continue;
+      }
+
+      let section = PathBuf::from(source.file_name().unwrap_or_default());
+
+      let section_report = match target {
+        DeployTarget::Local(root) => sync_local(&source, &root.join(&section))?,
+        DeployTarget::Rsync(root) => sync_rsync(
+          &source,
+          &format!("{root}/{}", section.display()),
+          self.get_config()?.identity_file.as_deref(),
+        )?,
+        DeployTarget::S3 { endpoint, bucket } => sync_s3(
+          &S3Client::new(endpoint, bucket, self.s3_credentials()?),
+          &source,
+          &section,
+        )?,
+      };
+
+      report.uploaded.extend(
+        section_report
+          .uploaded
+          .into_iter()
+          .map(|file| section.join(file)),
+      );
+      report.deleted.extend(
+        section_report
+          .deleted
+          .into_iter()
+          .map(|file| section.join(file)),
+      );
+    }
+
+    info!(
+      "Deployed {} files, deleted {} files",
+      report.uploaded.len(),
+      report.deleted.len()
+    );
+
+    Ok(report)
+  }
+
+  /// Reads the credentials for [DeployTarget::S3] from the config file
+  fn s3_credentials(&self) -> Result<Credentials> {
+    let config_file = self.get_config()?;
+
+    Ok(Credentials {
+      access_key_id: config_file
+        .awsaccesskeyid
+        .ok_or(Error::MissingConfig("awsaccesskeyid".to_owned()))?,
+      secret_key: config_file
+        .awssecretkey
+        .ok_or(Error::MissingConfig("awssecretkey".to_owned()))?,
+      region: config_file.awsregion.unwrap_or(DEFAULT_REGION.to_owned()),
+    })
+  }
+}
+
+/// Returns true for files which have to be uploaded last
+pub(super) fn is_index_file(relative_path: &Path) -> bool {
+  let mut components = relative_path.components();
+
+  match (components.next(), components.next()) {
+    (Some(first), None) => INDEX_FILES.iter().any(|index| first.as_os_str() == *index),
+    // index diffs (index-v2) reference apks as well
+    (Some(first), Some(_)) => first.as_os_str() == "diff",
+    _ => false,
+  }
+}
+
+/// Returns all files inside of a directory (recursively), relative to the directory
+pub(super) fn list_files(directory: &Path) -> Result<BTreeSet<PathBuf>> {
+  let mut files = BTreeSet::new();
+  let mut directories = vec![directory.to_path_buf()];
+
+  while let Some(current) = directories.pop() {
+    for entry in fs::read_dir(&current)? {
+      let path = entry?.path();
+
+      if path.is_dir() {
+        directories.push(path);
+      } else if let Ok(relative_path) = path.strip_prefix(directory) {
+        files.insert(relative_path.to_path_buf());
+      }
+    }
+  }
+
+  Ok(files)
+}
+
+/// Returns true if the file does not exist in `destination` or its content differs
+fn has_changed(source: &PathBuf, destination: &PathBuf) -> Result<bool> {
+  if !destination.is_file() || source.metadata()?.len() != destination.metadata()?.len() {
+    return Ok(true);
+  }
+
+  Ok(file_sha256(source)? != file_sha256(destination)?)
+}
+
+/// Syncs a directory into a local directory
+fn sync_local(source: &Path, destination: &Path) -> Result<DeployReport> {
+  let mut report = DeployReport::default();
+  let source_files = list_files(source)?;
+
+  let (index_files, other_files): (Vec<_>, Vec<_>) =
+    source_files.iter().partition(|file| is_index_file(file));
+
+  for file in other_files.into_iter().chain(index_files) {
+    let source_file = source.join(file);
+    let destination_file = destination.join(file);
+
+    if has_changed(&source_file, &destination_file)? {
+      debug!("Uploading {destination_file:?}");
+
+      if let Some(parent) = destination_file.parent() {
+        fs::create_dir_all(parent)?;
+      }
+      fs::copy(&source_file, &destination_file)?;
+      report.uploaded.push(file.clone());
+    }
+  }
+
+  if destination.is_dir() {
+    for file in list_files(destination)?.difference(&source_files) {
+      warn!("Deleting {:?} from deploy target", destination.join(file));
+      fs::remove_file(destination.join(file))?;
+      report.deleted.push(file.clone());
+    }
+  }
+
+  Ok(report)
+}
+
+/// Returns the `--rsh` command of rsync for an ssh key
+///
+/// rsync splits the command at spaces, but keeps quoted arguments together. Backslashes have no
+/// special meaning, so single quotes inside of the path are written inside of double quotes.
+pub(crate) fn remote_shell(identity_file: &str) -> String {
+  format!(
+    "ssh -oBatchMode=yes -oIdentitiesOnly=yes -i '{}'",
+    identity_file.replace('\'', r#"'"'"'"#)
+  )
+}
+
+/// Syncs a directory to an rsync destination over SSH
+///
+/// Runs rsync twice: first all files except the index files, then everything (including the
+/// index files) while deleting files which don't exist anymore.
+fn sync_rsync(
+  source: &Path,
+  destination: &str,
+  identity_file: Option<&str>,
+) -> Result<DeployReport> {
+  let mut report = DeployReport::default();
+
+  let index_excludes: Vec<String> = INDEX_FILES
+    .iter()
+    .map(|index| format!("/{index}"))
+    .chain(["/diff/".to_owned()])
+    .collect();
+
+  for (excludes, delete) in [(index_excludes.as_slice(), false), (&[][..], true)] {
+    let mut command = Command::new("rsync");
+    command
+      .args(["--archive", "--checksum", "--safe-links", "--out-format=%n"])
+      .arg(format!("{}/", source.display()))
+      .arg(format!("{destination}/"));
+
+    if delete {
+      command.arg("--delete-after");
+    }
+
+    for exclude in excludes {
+      command.arg("--exclude").arg(exclude);
+    }
+
+    if let Some(identity_file) = identity_file {
+      command.arg("-e").arg(remote_shell(identity_file));
+    }
+
+    let command_string = format!("rsync {source:?} {destination}");
+    debug!("Running {command:?}");
+
+    let output = command
+      .output()
+      .map_err(|_| Error::Run(command_string.clone()))?;
+
+    if !output.status.success() {
+      warn!(
+        "rsync failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+      );
+      return Err(Error::Run(command_string));
+    }
+
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+      if let Some(deleted) = line.strip_prefix("deleting ") {
+        report.deleted.push(PathBuf::from(deleted));
+      } else if !line.is_empty() && !line.ends_with('/') {
+        report.uploaded.push(PathBuf::from(line));
+      }
+    }
+  }
+
+  Ok(report)
+}
@@ -0,0 +1,304 @@
+//! Generation of build recipes from a local Android project, similar to `fdroid import`
+//!
+//! The result is a proposal which should be reviewed (e.g. the license can't be detected)
+//! before it is saved with [Repository::set_metadata](super::Repository::set_metadata):
+//! ```no_run
+//! # use std::path::{Path, PathBuf};
+//! # use fdroid::{import_project, Repository};
+//! let repository = Repository::new(PathBuf::from("/fdroid")).unwrap();
+//! let mut project = import_project(Path::new("/src/app")).unwrap();
+//! project.metadata.License = Some("GPL-3.0-only".to_owned());
+//!
+//! let package_name = project.package_name.unwrap();
+//! repository.set_metadata(&package_name, &project.metadata).unwrap();
+//! ```
+
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use log::{debug, info, warn};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::error::{Error, InvalidFile, Result};
+use crate::metadata::{AppMetadata, AutoUpdateMode, Builds, RepoType, UpdateCheckMode};
+
+use super::checkupdates::{
+  capture, run_git, GRADLE_VERSION_CODE, GRADLE_VERSION_NAME, MANIFEST_VERSION_CODE,
+  MANIFEST_VERSION_NAME,
+};
+use super::lint::is_valid_package_name;
+
+/// Flavors which are preferred for builds, as they usually exclude proprietary dependencies
+const FOSS_FLAVORS: [&str; 6] = ["fdroid", "foss", "libre", "floss", "oss", "free"];
+
+/// `include` statements of `settings.gradle(.kts)`
+static INCLUDE_REGEX: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r"(?m)^\s*include\b(.*)$").expect("invalid regex"));
+/// a single module of an `include` statement, e.g. `':app'`
+static MODULE_REGEX: LazyLock<Regex> =
+  LazyLock::new(|| Regex::new(r#"["']:?([^"']+)["']"#).expect("invalid regex"));
+/// the name of a block, e.g. `free` of `free` or of `create("free")`
+static BLOCK_NAME_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+  Regex::new(r#"(?:(?:create|register|maybeCreate)\s*\(\s*"([^"]+)"\s*\)|(\w+))\s*$"#)
+    .expect("invalid regex")
+});
+
+/// Result of [import_project]
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct ImportedProject {
+  /// `applicationId` (or `namespace`) of the app module or the package of its manifest
+  pub package_name: Option<String>,
+  /// proposed metadata with a single build
+  pub metadata: AppMetadata,
+  /// all gradle modules (e.g. `app` or `libs/core`), the root project is not included
+  pub modules: Vec<String>,
+  /// directory of the app module relative to the project, empty for the root project
+  pub app_module: String,
+  /// all product flavors of the app module
+  pub flavors: Vec<String>,
+  /// true if the app module contains native code
+  pub uses_ndk: bool,
+  /// problems of the proposal which have to be fixed by hand before building
+  pub warnings: Vec<String>,
+}
+
+/// Inspects a local Android project and proposes the metadata to build it
+///
+/// Detects the app module (using `settings.gradle`), its version, product flavors and native
+/// code. For git checkouts, `Repo` is the `origin` remote, `commit` is the tag (or commit) of
+/// `HEAD`, `submodules` is enabled if `.gitmodules` exists and [UpdateCheckMode::Tags] is
+/// used if the repository has tags. An invalid package name or native code without
+/// `ndkVersion` is reported in [ImportedProject::warnings].
+///
+/// # Error
+/// Returns an error if `project_dir` is not a directory or no android application module exists
+pub fn import_project(project_dir: &Path) -> Result<ImportedProject> {
+  if !project_dir.is_dir() {
+    return Err(Error::NotADirectory(project_dir.to_path_buf()));
+  }
+  info!("Importing {project_dir:?}");
+
+  let modules = read_modules(project_dir);
+  // the root project is only the app module if there are no others
+  let (app_module, build_file) = modules
+    .iter()
+    .cloned()
+    .chain(["".to_owned()])
+    .find_map(|module| {
+      let build_file = read_build_file(&project_dir.join(&module))?;
+      is_application(&build_file).then_some((module, build_file))
+    })
+    .ok_or(Error::InvalidFile(InvalidFile::with_reason(
+      project_dir.to_path_buf(),
+      "No android application module found",
+    )))?;
+  debug!("Found app module \"{app_module}\"");
+
+  let module_dir = project_dir.join(&app_module);
+  let manifest = ["src/main/AndroidManifest.xml", "AndroidManifest.xml"]
+    .into_iter()
+    .find_map(|path| fs::read_to_string(module_dir.join(path)).ok())
+    .unwrap_or_default();
+
+  let gradle_or_manifest = |gradle_regex: &str, manifest_regex: &str| -> Result<Option<String>> {
+    Ok(capture(gradle_regex, &build_file)?.or(capture(manifest_regex, &manifest)?))
+  };
+
+  let package_name = gradle_or_manifest(
+    r#"applicationId\s*=?\s*["']([^"']+)["']"#,
+    r#"<manifest[^>]*\spackage\s*=\s*["']([^"']+)["']"#,
+  )?
+  .or(capture(
+    r#"namespace\s*=?\s*["']([^"']+)["']"#,
+    &build_file,
+  )?);
+  let version_code = gradle_or_manifest(GRADLE_VERSION_CODE, MANIFEST_VERSION_CODE)?;
+  let version_name = gradle_or_manifest(GRADLE_VERSION_NAME, MANIFEST_VERSION_NAME)?;
+
+  let flavors = block(&build_file, "productFlavors")
+    .map(block_names)
+    .unwrap_or_default();
+  let gradle_flavor = FOSS_FLAVORS
+    .iter()
+    .find_map(|foss| {
+      flavors
+        .iter()
+        .find(|flavor| flavor.eq_ignore_ascii_case(foss))
+    })
+    .or(flavors.first())
+    .cloned()
+    .unwrap_or("yes".to_owned());
+
+  let uses_ndk = build_file.contains("externalNativeBuild")
+    || ["src/main/cpp", "src/main/jni", "jni"]
+      .iter()
+      .any(|path| module_dir.join(path).is_dir());
+  let ndk = capture(r#"ndkVersion\s*=?\s*["']([^"']+)["']"#, &build_file)?;
+
+  let mut warnings = vec![];
+  if let Some(package_name) = package_name
+    .as_deref()
+    .filter(|package_name| !is_valid_package_name(package_name))
+  {
+    warnings.push(format!("\"{package_name}\" is not a valid package name"));
+  }
+  if uses_ndk && ndk.is_none() {
+    warnings
+      .push("The app uses native code, but no ndkVersion is set, add ndk to the build".to_owned());
+  }
+  warnings.iter().for_each(|warning| warn!("{warning}"));
+
+  let run = |args: &[&str]| {
+    run_git(project_dir, args)
+      .ok()
+      .map(|output| output.trim().to_owned())
+      .filter(|output| !output.is_empty())
+  };
+  // only the root of a repository, not a directory inside of another repository
+  let is_git = run(&["rev-parse", "--show-toplevel"]).is_some_and(|toplevel| {
+    Path::new(&toplevel).canonicalize().ok() == project_dir.canonicalize().ok()
+  });
+  let git = |args: &[&str]| if is_git { run(args) } else { None };
+  let repo = git(&["remote", "get-url", "origin"]);
+  let commit =
+    git(&["describe", "--tags", "--exact-match", "HEAD"]).or(git(&["rev-parse", "HEAD"]));
+  let has_tags = git(&["tag", "--list"]).is_some();
+
+  let build = Builds {
+    versionName: version_name.clone(),
+    versionCode: version_code.clone(),
+    commit,
+    subdir: Some(app_module.clone()).filter(|subdir| !subdir.is_empty()),
+    submodules: project_dir.join(".gitmodules").is_file().then_some(true),
+    gradle: Some(vec![gradle_flavor]),
+    ndk,
+    ..Default::default()
+  };
+
+  let metadata = AppMetadata {
+    RepoType: is_git.then_some(RepoType::Git),
+    SourceCode: repo
+      .as_deref()
+      .filter(|repo| repo.starts_with("https://"))
+      .map(|repo| repo.trim_end_matches(".git").to_owned()),
+    Repo: repo,
+    Builds: Some(vec![build]),
+    AutoUpdateMode: Some(AutoUpdateMode::Version(None)),
+    UpdateCheckMode: Some(if has_tags {
+      UpdateCheckMode::Tags(None)
+    } else {
+      UpdateCheckMode::RepoManifest(None)
+    }),
+    CurrentVersion: version_name,
+    CurrentVersionCode: version_code,
+    ..Default::default()
+  };
+
+  Ok(ImportedProject {
+    package_name,
+    metadata,
+    modules,
+    app_module,
+    flavors,
+    uses_ndk,
+    warnings,
+  })
+}
+
+/// Returns the directories of all modules included in `settings.gradle(.kts)`
+fn read_modules(project_dir: &Path) -> Vec<String> {
+  let Some(settings) = ["settings.gradle", "settings.gradle.kts"]
+    .into_iter()
+    .find_map(|file| fs::read_to_string(project_dir.join(file)).ok())
+  else {
+    return vec![];
+  };
+
+  let mut modules = vec![];
+  for captures in INCLUDE_REGEX.captures_iter(&settings) {
+    for captures in MODULE_REGEX.captures_iter(captures.get(1).map_or("", |found| found.as_str())) {
+      let module = captures[1].replace(':', "/");
+      // keep the order of settings.gradle, the first application module is used
+      if !modules.contains(&module) {
+        modules.push(module);
+      }
+    }
+  }
+
+  modules
+}
+
+fn read_build_file(module_dir: &Path) -> Option<String> {
+  ["build.gradle", "build.gradle.kts"]
+    .into_iter()
+    .find_map(|file| fs::read_to_string(module_dir.join(file)).ok())
+}
+
+/// Returns true if the build file applies the android application plugin
+///
+/// Declarations with `apply false` (usually inside of the root project) are ignored.
+fn is_application(build_file: &str) -> bool {
+  build_file.lines().any(|line| {
+    (line.contains("android.application") || line.contains("androidApplication"))
+      && !line.contains("apply false")
+  })
+}
+
+/// Returns the content of the first block `<name> { ... }`
+fn block<'a>(content: &'a str, name: &str) -> Option<&'a str> {
+  let start = Regex::new(&format!(r"\b{}\s*\{{", regex::escape(name)))
+    .expect("invalid regex")
+    .find(content)?
+    .end();
+
+  let mut depth = 1;
+  for (index, char) in content[start..].char_indices() {
+    match char {
+      '{' => depth += 1,
+      '}' => {
+        depth -= 1;
+        if depth == 0 {
+          return Some(&content[start..start + index]);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  None
+}
+
+/// Returns the names of all blocks directly inside of `content`, e.g. `free` of `free { }` or
+/// of `create("free") { }`
+fn block_names(content: &str) -> Vec<String> {
+  let mut names = vec![];
+  let mut depth = 0;
+  let mut statement = String::new();
+
+  for char in content.chars() {
+    match char {
+      '{' => {
+        if depth == 0 {
+          // the name is on the last line before the brace, which can be on its own line
+          let last_line = statement.trim_end().rsplit('\n').next().unwrap_or_default();
+          if let Some(captures) = BLOCK_NAME_REGEX.captures(last_line) {
+            if let Some(found) = captures.get(1).or(captures.get(2)) {
+              names.push(found.as_str().to_owned());
+            }
+          }
+        }
+        depth += 1;
+      }
+      '}' => {
+        depth -= 1;
+        statement.clear();
+      }
+      char if depth == 0 => statement.push(char),
+      _ => {}
+    }
+  }
+
+  names
+}
@@ -0,0 +1,316 @@
+//! Native linter for metadata files, similar to `fdroid lint`
+//!
+//! See [Build Metadata Reference](https://f-droid.org/en/docs/Build_Metadata_Reference/)
+
+use std::fs;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use log::info;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+use crate::metadata::{AppMetadata, UpdateCheckMode};
+
+use super::{Repository, UpdateCheckData, VercodeOperation};
+
+/// Maximum length of [AppMetadata::Name]
+const MAX_NAME_LENGTH: usize = 50;
+/// Maximum length of [AppMetadata::Summary]
+const MAX_SUMMARY_LENGTH: usize = 80;
+
+/// Returns true if `package_name` is a valid android package name, e.g. `org.example.app`
+///
+/// Valid names are safe to be used as file names in the repository.
+pub fn is_valid_package_name(package_name: &str) -> bool {
+  static PACKAGE_NAME_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^[A-Za-z][A-Za-z0-9_]*(\.[A-Za-z][A-Za-z0-9_]*)+$").expect("invalid regex")
+  });
+
+  PACKAGE_NAME_REGEX.is_match(package_name)
+}
+
+/// How severe a [LintIssue] is
+#[derive(Debug, Clone, Copy, Serialize, Ord, PartialOrd, Eq, PartialEq)]
+pub enum LintSeverity {
+  /// The metadata works, but should be improved
+  Warning,
+  /// The metadata is invalid and will break builds or update checks
+  Error,
+}
+
+/// A single problem found by the linter
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct LintIssue {
+  pub package_name: String,
+  /// name of the metadata field, e.g. `Summary` or `Builds.versionCode`
+  pub field: String,
+  pub severity: LintSeverity,
+  pub message: String,
+}
+
+/// Result of [Repository::lint]
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct LintReport {
+  pub issues: Vec<LintIssue>,
+}
+
+impl LintReport {
+  /// Returns true if no issue is an [LintSeverity::Error]
+  pub fn is_ok(&self) -> bool {
+    self
+      .issues
+      .iter()
+      .all(|issue| issue.severity != LintSeverity::Error)
+  }
+}
+
+impl Repository {
+  /// Lints the metadata files of all apps
+  ///
+  /// Files which can't be parsed are reported as [LintSeverity::Error].
+  ///
+  /// # Error
+  /// Returns an error if the metadata directory can't be read
+  pub fn lint(&self) -> Result<LintReport> {
+    info!("Linting all metadata files");
+
+    let mut report = LintReport::default();
+
+    if !self.metadata_path().is_dir() {
+      return Ok(report);
+    }
+
+    let mut package_names: Vec<String> = fs::read_dir(self.metadata_path())?
+      .filter_map(|entry| entry.ok())
+      .map(|entry| entry.path())
+      .filter(|path| path.extension().is_some_and(|extension| extension == "yml"))
+      .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+      .collect();
+    package_names.sort();
+
+    for package_name in package_names {
+      match self.metadata(&package_name) {
+        Ok(metadata) => report
+          .issues
+          .extend(self.lint_metadata(&package_name, &metadata)),
+        Err(err) => report.issues.push(LintIssue {
+          package_name,
+          field: String::new(),
+          severity: LintSeverity::Error,
+          message: err.to_string(),
+        }),
+      }
+    }
+
+    Ok(report)
+  }
+
+  /// Lints the metadata of a single app
+  pub fn lint_metadata(&self, package_name: &str, metadata: &AppMetadata) -> Vec<LintIssue> {
+    let mut issues = vec![];
+    let mut issue = |field: &str, severity: LintSeverity, message: String| {
+      issues.push(LintIssue {
+        package_name: package_name.to_owned(),
+        field: field.to_owned(),
+        severity,
+        message,
+      })
+    };
+
+    if !is_valid_package_name(package_name) {
+      issue(
+        "",
+        LintSeverity::Error,
+        format!("\"{package_name}\" is not a valid package name"),
+      );
+    }
+
+    if let Some(name) = &metadata.Name {
+      if name.chars().count() > MAX_NAME_LENGTH {
+        issue(
+          "Name",
+          LintSeverity::Warning,
+          format!("Name is longer than {MAX_NAME_LENGTH} characters"),
+        );
+      }
+    }
+
+    if let Some(summary) = &metadata.Summary {
+      if summary.chars().count() > MAX_SUMMARY_LENGTH {
+        issue(
+          "Summary",
+          LintSeverity::Warning,
+          format!("Summary is longer than {MAX_SUMMARY_LENGTH} characters"),
+        );
+      }
+      if summary.trim_end().ends_with('.') {
+        issue(
+          "Summary",
+          LintSeverity::Warning,
+          "Summary should not end with a period".to_owned(),
+        );
+      }
+    }
+
+    if metadata.License.as_deref().is_none_or(str::is_empty) {
+      issue(
+        "License",
+        LintSeverity::Warning,
+        "No license is set".to_owned(),
+      );
+    }
+
+    if metadata
+      .Categories
+      .as_ref()
+      .is_none_or(|categories| categories.is_empty())
+    {
+      issue(
+        "Categories",
+        LintSeverity::Warning,
+        "No categories are set".to_owned(),
+      );
+    }
+
+    for (field, url) in [
+      ("WebSite", &metadata.WebSite),
+      ("SourceCode", &metadata.SourceCode),
+      ("IssueTracker", &metadata.IssueTracker),
+      ("Translation", &metadata.Translation),
+      ("Changelog", &metadata.Changelog),
+      ("Donate", &metadata.Donate),
+      ("AuthorWebSite", &metadata.AuthorWebSite),
+    ] {
+      let Some(url) = url.as_deref().filter(|url| !url.is_empty()) else {
+        continue;
+      };
+
+      if url.starts_with("http://") {
+        issue(
+          field,
+          LintSeverity::Warning,
+          format!("\"{url}\" should use https"),
+        );
+      } else if !url.starts_with("https://") {
+        issue(
+          field,
+          LintSeverity::Error,
+          format!("\"{url}\" is not a valid url"),
+        );
+      }
+    }
+
+    if let Some(email) = metadata
+      .AuthorEmail
+      .as_deref()
+      .filter(|email| !email.is_empty())
+    {
+      if !email.contains('@') {
+        issue(
+          "AuthorEmail",
+          LintSeverity::Error,
+          format!("\"{email}\" is not a valid e-mail address"),
+        );
+      }
+    }
+
+    if metadata.Repo.is_some() && metadata.RepoType.is_none() {
+      issue(
+        "RepoType",
+        LintSeverity::Error,
+        "Repo is set but RepoType is missing".to_owned(),
+      );
+    }
+
+    if metadata.UpdateCheckMode == Some(UpdateCheckMode::Http) && metadata.UpdateCheckData.is_none()
+    {
+      issue(
+        "UpdateCheckData",
+        LintSeverity::Error,
+        "UpdateCheckMode HTTP requires UpdateCheckData".to_owned(),
+      );
+    }
+
+    if let Some(Err(Error::UpdateCheck(reason))) = metadata
+      .UpdateCheckData
+      .as_deref()
+      .map(UpdateCheckData::from_str)
+    {
+      issue("UpdateCheckData", LintSeverity::Error, reason);
+    }
+
+    for operation in metadata.VercodeOperation.iter().flatten() {
+      if let Err(err) = operation.parse::<VercodeOperation>() {
+        issue(
+          "VercodeOperation",
+          LintSeverity::Error,
+          format!("\"{operation}\": {err}"),
+        );
+      }
+    }
+
+    if let Some(current_version_code) = &metadata.CurrentVersionCode {
+      if current_version_code.parse::<u64>().is_err() {
+        issue(
+          "CurrentVersionCode",
+          LintSeverity::Error,
+          format!("\"{current_version_code}\" is not a valid version code"),
+        );
+      }
+    }
+
+    for build in metadata.Builds.iter().flatten() {
+      if build.disable.is_some() {
+        continue;
+      }
+
+      match &build.versionCode {
+        Some(version_code) if version_code.parse::<u64>().is_err() => issue(
+          "Builds.versionCode",
+          LintSeverity::Error,
+          format!("\"{version_code}\" is not a valid version code"),
+        ),
+        None => issue(
+          "Builds.versionCode",
+          LintSeverity::Error,
+          "Build has no versionCode".to_owned(),
+        ),
+        _ => {}
+      }
+
+      if build.commit.as_deref().is_none_or(str::is_empty) {
+        issue(
+          "Builds.commit",
+          LintSeverity::Error,
+          "Build has no commit".to_owned(),
+        );
+      }
+
+      for reference in build.srclib_references() {
+        match reference {
+          Ok(reference)
+            if !self
+              .srclib_path(&reference.name)
+              .is_ok_and(|srclib_path| srclib_path.is_file()) =>
+          {
+            issue(
+              "Builds.srclibs",
+              LintSeverity::Error,
+              format!("Srclib \"{}\" does not exist", reference.name),
+            )
+          }
+          Err(reference) => issue(
+            "Builds.srclibs",
+            LintSeverity::Error,
+            format!("\"{reference}\" is not a valid srclib, expected Name@ref"),
+          ),
+          _ => {}
+        }
+      }
+    }
+
+    issues
+  }
+}
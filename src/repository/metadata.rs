@@ -1,5 +1,6 @@
 //! For Working with the [metadata](https://f-droid.org/en/docs/Build_Metadata_Reference/) of a package.
 
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::{fs, fs::File, io::Read};
 
@@ -8,11 +9,11 @@ use serde::{Deserialize, Serialize};
 
 use crate::error::*;
 
-use super::Repository;
+use super::{FieldChange, Repository, RepositoryEvent};
 
 /// [DTO](https://en.wikipedia.org/wiki/Data_transfer_object) containing all the
 /// [metadata](https://f-droid.org/en/docs/Build_Metadata_Reference/) for a single package
-#[derive(Debug, Clone, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
 #[allow(non_snake_case)]
 pub struct AppMetadata {
   /// Any number of categories for the application to be placed in. There is no fixed list of categories - both the client and the web site will automatically show any categories that exist in any applications. However, if your metadata is intended for the main F-Droid repository, you should use one of the existing categories (Connectivity,Development, Games,Graphics,Internet,Money,Multimedia,Navigation,Phone & SMS, Reading,Science & Education,Security,Sports & Health,System,Theming, Time,Writing), or discuss the proposal to add a new one. Categories must be a list of items, even if there is just one.
@@ -163,7 +164,7 @@ pub struct AppMetadata {
   /// ```
   ///
   /// See [Builds](https://f-droid.org/en/docs/Build_Metadata_Reference/#Builds)
-  pub Builds: Option<Builds>,
+  pub Builds: Option<Vec<Builds>>,
   /// When making automated binary repositories with fdroid update, it is generally easy to find out the expected signing key for the APKs that are gathered. AllowedAPKSigningKeys lets the repo operator set the expected signing keys, then fdroid update will check that the APKs are signed by one of those keys. If not, the mismatched APKs will not be included in the repo. If fdroid update --delete-unknown is specified, the mismatched APKs will be deleted. Then an automated process can be used to download newer APKs to the repo, and they will only be included if they have a known good signature. The value is a lowercase hex value of the SHA-256 fingerprint of the signing certificate. This can be fetched using:
   /// `apksigner verify --print-certs example.apk | grep SHA-256`
   ///
@@ -171,8 +172,18 @@ pub struct AppMetadata {
   pub AllowedAPKSigningKeys: Option<String>,
   /// This is optional - if present, it contains a comma-separated list of any of the following values, describing an anti-feature the application has. It is a good idea to mention the reasons for the anti-feature(s) in the description.
   ///
+  /// A single value, a list and a map (from anti-feature to reasons) are read. Each
+  /// anti-feature maps to its reasons by locale (a reason without locale is `en-US`), which
+  /// are empty if no reason is given. It is written as a list if no reasons exist and as a
+  /// map otherwise. See [AntiFeatureReasons].
+  ///
   /// See [AntiFeatures](https://f-droid.org/en/docs/Build_Metadata_Reference/#AntiFeatures)
-  pub AntiFeatures: Option<AntiFeature>,
+  #[serde(
+    default,
+    deserialize_with = "anti_features",
+    serialize_with = "serialize_anti_features"
+  )]
+  pub AntiFeatures: Option<BTreeMap<AntiFeature, AntiFeatureReasons>>,
   /// If this field is present, the application does not get put into the public index. This allows metadata to be retained while an application is temporarily disabled from being published. The value should be a description of why the application is disabled. No APKs or source code archives are deleted: to purge an APK see the Build Version section or delete manually for developer builds. The field is therefore used when an app has outlived it’s usefulness, because the source tarball is retained.
   ///
   /// See [Disabled](https://f-droid.org/en/docs/Build_Metadata_Reference/#Disabled)
@@ -193,18 +204,19 @@ pub struct AppMetadata {
   ///
   /// See [UpdateCheckMode](https://f-droid.org/en/docs/Build_Metadata_Reference/#UpdateCheckMode)
   pub UpdateCheckMode: Option<UpdateCheckMode>,
-  /// Operation to be applied to the vercode obtained by the defined UpdateCheckMode. %c will be replaced by the actual vercode, and the whole string will be passed to python’s eval function.
-  ///
-  /// Especially useful with apps that we want to compile for different ABIs, but whose vercodes don’t always have trailing zeros. For example, with VercodeOperation set at something like %c*10 + 4, we will be able to track updates and build up to four different versions of every upstream version.
+  /// When checking for updates (via UpdateCheckMode) this can be used to specify a regex which, if matched against the version name, causes that version to be ignored. For example, ‘beta’ could be specified to ignore version names that include that text.
   ///
   /// See [UpdateCheckIgnore](https://f-droid.org/en/docs/Build_Metadata_Reference/#UpdateCheckIgnore)
   pub UpdateCheckIgnore: Option<String>,
-  /// When checking for updates (via UpdateCheckMode) this can be used to specify a regex which, if matched against the version name, causes that version to be ignored. For example, ‘beta’ could be specified to ignore version names that include that text.
+  /// Operations to be applied to the vercode obtained by the defined UpdateCheckMode. %c will be replaced by the actual vercode, and the whole string will be evaluated as an arithmetic expression (see [VercodeOperation](crate::VercodeOperation)).
+  ///
+  /// Especially useful with apps that we want to compile for different ABIs, but whose vercodes don’t always have trailing zeros. For example, with VercodeOperation set at something like %c*10 + 4, we will be able to track updates and build up to four different versions of every upstream version. A list of operations (e.g. one per ABI) generates one version code per operation.
   ///
-  /// Only Available with [UpdateCheckMode::Http].
+  /// A single operation is also accepted as a plain string.
   ///
   /// See [VercodeOperation](https://f-droid.org/en/docs/Build_Metadata_Reference/#VercodeOperation)
-  pub VercodeOperation: Option<String>,
+  #[serde(default, deserialize_with = "string_or_list")]
+  pub VercodeOperation: Option<Vec<String>>,
   /// When checking for updates (via [UpdateCheckMode]) this can be used to specify the package name to search for. Useful when apps have a static package name but change it programmatically in some app flavors, by e.g. appending “.open” or “.free” at the end of the package name.
   ///
   /// You can also use Ignore to ignore package name searching. This should only be used in some specific cases, for example if the app’s build.gradle file does not contain the package name.
@@ -266,9 +278,99 @@ pub struct AppMetadata {
   pub NoSourceSince: Option<String>,
 }
 
+/// Deserializes a single string as a list with one entry
+fn string_or_list<'de, D: serde::Deserializer<'de>>(
+  deserializer: D,
+) -> std::result::Result<Option<Vec<String>>, D::Error> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum StringOrList {
+    String(String),
+    List(Vec<String>),
+  }
+
+  Ok(
+    Option::<StringOrList>::deserialize(deserializer)?.map(|value| match value {
+      StringOrList::String(value) => vec![value],
+      StringOrList::List(values) => values,
+    }),
+  )
+}
+
+/// Deserializes [AppMetadata::AntiFeatures] from all formats supported by fdroidserver
+fn anti_features<'de, D: serde::Deserializer<'de>>(
+  deserializer: D,
+) -> std::result::Result<Option<BTreeMap<AntiFeature, AntiFeatureReasons>>, D::Error> {
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum Reasons {
+    /// a reason without locale
+    Text(String),
+    Localized(AntiFeatureReasons),
+  }
+
+  #[derive(Deserialize)]
+  #[serde(untagged)]
+  enum AntiFeatures {
+    /// a single anti-feature or a comma separated list
+    String(String),
+    List(Vec<AntiFeature>),
+    /// anti-features with their reasons
+    Map(BTreeMap<AntiFeature, Option<Reasons>>),
+  }
+
+  let without_reasons = |anti_features: Vec<AntiFeature>| {
+    anti_features
+      .into_iter()
+      .map(|anti_feature| (anti_feature, BTreeMap::new()))
+      .collect()
+  };
+
+  Ok(match Option::<AntiFeatures>::deserialize(deserializer)? {
+    None => None,
+    Some(AntiFeatures::String(value)) => Some(without_reasons(
+      value
+        .split(',')
+        .map(|name| {
+          AntiFeature::deserialize(serde::de::value::StrDeserializer::<D::Error>::new(
+            name.trim(),
+          ))
+        })
+        .collect::<std::result::Result<_, _>>()?,
+    )),
+    Some(AntiFeatures::List(values)) => Some(without_reasons(values)),
+    Some(AntiFeatures::Map(values)) => Some(
+      values
+        .into_iter()
+        .map(|(anti_feature, reasons)| {
+          let reasons = match reasons {
+            None => BTreeMap::new(),
+            Some(Reasons::Text(reason)) => BTreeMap::from([("en-US".to_owned(), reason)]),
+            Some(Reasons::Localized(reasons)) => reasons,
+          };
+          (anti_feature, reasons)
+        })
+        .collect(),
+    ),
+  })
+}
+
+/// Serializes [AppMetadata::AntiFeatures] as a list, unless it contains reasons
+fn serialize_anti_features<S: serde::Serializer>(
+  anti_features: &Option<BTreeMap<AntiFeature, AntiFeatureReasons>>,
+  serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+  match anti_features {
+    Some(anti_features) if anti_features.values().all(BTreeMap::is_empty) => {
+      serializer.collect_seq(anti_features.keys())
+    }
+    anti_features => anti_features.serialize(serializer),
+  }
+}
+
 /// [DTO](https://en.wikipedia.org/wiki/Data_transfer_object) containing all the details for a single
 /// [build](https://f-droid.org/en/docs/Build_Metadata_Reference/#Builds)
-#[derive(Clone, Debug, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
 #[allow(non_snake_case)]
 pub struct Builds {
   // Required
@@ -415,8 +517,7 @@ pub struct Builds {
 ///
 /// During Deserialization, if a categories does not match any category defined in the enum,
 /// it automatically gets assigned to [Category::Custom]
-#[derive(Clone, Serialize, Deserialize, Debug, Ord, PartialOrd, Eq, PartialEq)]
-#[serde(untagged)]
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub enum Category {
   Connectivity,
   Development,
@@ -426,13 +527,10 @@ pub enum Category {
   Money,
   Multimedia,
   Navigation,
-  #[serde(rename = "Phone & SMS")]
   PhoneSms,
   Reading,
-  #[serde(rename = "Science & Education")]
   ScienceEducation,
   Security,
-  #[serde(rename = "Sports & Health")]
   SportsHealth,
   System,
   Theming,
@@ -441,6 +539,76 @@ pub enum Category {
   Custom(String),
 }
 
+impl Category {
+  /// All predefined categories
+  const PREDEFINED: [Self; 17] = [
+    Self::Connectivity,
+    Self::Development,
+    Self::Games,
+    Self::Graphics,
+    Self::Internet,
+    Self::Money,
+    Self::Multimedia,
+    Self::Navigation,
+    Self::PhoneSms,
+    Self::Reading,
+    Self::ScienceEducation,
+    Self::Security,
+    Self::SportsHealth,
+    Self::System,
+    Self::Theming,
+    Self::Time,
+    Self::Writing,
+  ];
+
+  /// The name used in metadata files and the index, e.g. `Phone & SMS`
+  pub fn name(&self) -> &str {
+    match self {
+      Self::Connectivity => "Connectivity",
+      Self::Development => "Development",
+      Self::Games => "Games",
+      Self::Graphics => "Graphics",
+      Self::Internet => "Internet",
+      Self::Money => "Money",
+      Self::Multimedia => "Multimedia",
+      Self::Navigation => "Navigation",
+      Self::PhoneSms => "Phone & SMS",
+      Self::Reading => "Reading",
+      Self::ScienceEducation => "Science & Education",
+      Self::Security => "Security",
+      Self::SportsHealth => "Sports & Health",
+      Self::System => "System",
+      Self::Theming => "Theming",
+      Self::Time => "Time",
+      Self::Writing => "Writing",
+      Self::Custom(name) => name,
+    }
+  }
+}
+
+impl From<&str> for Category {
+  fn from(name: &str) -> Self {
+    Self::PREDEFINED
+      .into_iter()
+      .find(|category| category.name() == name)
+      .unwrap_or(Self::Custom(name.to_owned()))
+  }
+}
+
+impl Serialize for Category {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.name())
+  }
+}
+
+impl<'de> Deserialize<'de> for Category {
+  fn deserialize<D: serde::Deserializer<'de>>(
+    deserializer: D,
+  ) -> std::result::Result<Self, D::Error> {
+    String::deserialize(deserializer).map(|name| Self::from(name.as_str()))
+  }
+}
+
 /// The type of repository - for automatic building from source. If this is not specified, automatic building is disabled for this application.
 ///
 /// See [documentation](https://f-droid.org/en/docs/Build_Metadata_Reference/#RepoType)
@@ -460,6 +628,9 @@ pub enum RepoType {
   Srclib,
 }
 
+/// Reasons for an anti-feature of [AppMetadata::AntiFeatures] by locale, e.g. `en-US`
+pub type AntiFeatureReasons = BTreeMap<String, String>;
+
 /// Features of the application that hinders the user.
 ///
 /// See [anti-feature](https://en.wiktionary.org/wiki/anti-feature)
@@ -493,7 +664,7 @@ pub enum AntiFeature {
 /// This determines the method using for determining when new releases are available - in other words, the updating of the CurrentVersion and CurrentVersionCode fields in the metadata by the fdroid checkupdates process.
 ///
 /// See [UpdateCheckMode](https://f-droid.org/en/docs/Build_Metadata_Reference/#UpdateCheckMode)
-#[derive(Clone, Serialize, Deserialize, Debug, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub enum UpdateCheckMode {
   /// No checking is done because there’s no appropriate automated way of doing so. Updates should be checked for manually. Use this, for example, when deploying unstable or patched versions; when builds are done in a directory different to where the AndroidManifest.xml is; if the developers use the Gradle build system and store version info in a separate file; if the developers make a new branch for each release and don’t make tags; or if you’ve changed the package name or version code logic.
   None,
@@ -502,7 +673,9 @@ pub enum UpdateCheckMode {
   /// At the most recent commit, the AndroidManifest.xml and build.gradle files are looked for in the directory where they were found in the the most recent build. The appropriateness of this method depends on the development process used by the application’s developers. You should not specify this method unless you’re sure it’s appropriate. For example, some developers bump the version when commencing development instead of when publishing. It will return an error if the AndroidManifest.xml has moved to a different directory or if the package name has changed. The current version that it gives may not be accurate, since not all versions are fit to be published. Therefore, before building, it is often necessary to check if the current version has been published somewhere by the upstream developers, either by checking for APKs that they distribute or for tags in the source code repository.
   ///
   /// It currently works for every repository type to different extents, except the srclib repo type. For git, git-svn and hg repo types, you may use “RepoManifest/yourbranch” as UpdateCheckMode so that “yourbranch” would be the branch used in place of the default one. The default values are “master” for git, “default” for hg and none for git-svn (it stays in the same branch). On the other hand, branch support hasn’t been implemented yet in bzr and svn, but RepoManifest may still be used without it.
-  RepoManifest,
+  ///
+  /// Contains the branch, if one is specified.
+  RepoManifest(Option<String>),
   /// For svn and git-svn repositories, especially those who don’t have a bundled AndroidManifest.xml file, the Tags and RepoManifest checks will not work, since there is no version information to obtain. But, for those apps who automate their build process with the commit ref that HEAD points to, RepoTrunk will set the CurrentVersion and CurrentVersionCode to that number.
   RepoTrunk,
   /// The AndroidManifest.xml and build.gradle files in all tagged revisions in the source repository are checked, looking for the highest version code. The appropriateness of this method depends on the development process used by the application’s developers. You should not specify this method unless you’re sure it’s appropriate. It shouldn’t be used if the developers like to tag unstable versions or are known to forget to tag releases. Like RepoManifest, it will not return the correct value if the directory containing the AndroidManifest.xml has moved. Despite these caveats, it is the often the favourite UpdateCheckMode.
@@ -512,25 +685,126 @@ pub enum UpdateCheckMode {
   /// Optionally append a regex pattern at the end - separated with a space - to only check the tags matching said pattern. Useful when apps tag non-release versions such as X.X-alpha, so you can filter them out with something like `.*[0-9]$` which requires tag names to end with a digit. Example: UpdateCheckMode: Tags `.*[0-9]$`
   ///
   /// Optionally UpdateCheckData can be specified to extract version code and name from repository files you specify (instead of relying on the defaults used to match against otherwise, which in most cases is build.gradle or AndroidManifest.xml).
-  Tags,
+  ///
+  /// Contains the regex pattern, if one is specified.
+  Tags(Option<String>),
   /// HTTP requests are used to determine the current version code and version name. This is controlled by the UpdateCheckData field, which is of the form urlcode|excode|urlver|exver.
   ///
   /// Firstly, if urlcode is non-empty, the document from that URL is retrieved, and matched against the regular expression excode, with the first group becoming the version code.
   ///
   /// Secondly, if urlver is non-empty, the document from that URL is retrieved, and matched against the regular expression exver, with the first group becoming the version name. The urlver field can be set to simply ‘.’ which says to use the same document returned for the version code again, rather than retrieving a different one.
-  #[serde(rename = "HTTP")]
   Http,
 }
 
+impl UpdateCheckMode {
+  /// The value used in metadata files, e.g. `Tags .*[0-9]$` or `RepoManifest/stable`
+  pub fn name(&self) -> String {
+    match self {
+      Self::None => "None".to_owned(),
+      Self::Static => "Static".to_owned(),
+      Self::RepoManifest(None) => "RepoManifest".to_owned(),
+      Self::RepoManifest(Some(branch)) => format!("RepoManifest/{branch}"),
+      Self::RepoTrunk => "RepoTrunk".to_owned(),
+      Self::Tags(None) => "Tags".to_owned(),
+      Self::Tags(Some(pattern)) => format!("Tags {pattern}"),
+      Self::Http => "HTTP".to_owned(),
+    }
+  }
+}
+
+impl TryFrom<&str> for UpdateCheckMode {
+  type Error = String;
+
+  fn try_from(name: &str) -> std::result::Result<Self, Self::Error> {
+    let non_empty = |value: &str| Some(value.trim().to_owned()).filter(|value| !value.is_empty());
+
+    match name {
+      "None" => Ok(Self::None),
+      "Static" => Ok(Self::Static),
+      "RepoTrunk" => Ok(Self::RepoTrunk),
+      "HTTP" => Ok(Self::Http),
+      "RepoManifest" => Ok(Self::RepoManifest(None)),
+      "Tags" => Ok(Self::Tags(None)),
+      _ => {
+        if let Some(branch) = name.strip_prefix("RepoManifest/") {
+          Ok(Self::RepoManifest(non_empty(branch)))
+        } else if let Some(pattern) = name.strip_prefix("Tags ") {
+          Ok(Self::Tags(non_empty(pattern)))
+        } else {
+          Err(format!("unknown UpdateCheckMode \"{name}\""))
+        }
+      }
+    }
+  }
+}
+
+impl Serialize for UpdateCheckMode {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.name())
+  }
+}
+
+impl<'de> Deserialize<'de> for UpdateCheckMode {
+  fn deserialize<D: serde::Deserializer<'de>>(
+    deserializer: D,
+  ) -> std::result::Result<Self, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    Self::try_from(name.as_str()).map_err(serde::de::Error::custom)
+  }
+}
+
 /// This determines the method used for auto-generating new builds when new releases are available - in other words, adding a new Build Version line to the metadata. This happens in conjunction with the UpdateCheckMode functionality - i.e. when an update is detected by that, it is also processed by this.
 ///
 /// See [AutoUpdateMode](https://f-droid.org/en/docs/Build_Metadata_Reference/#AutoUpdateMode)
-#[derive(Clone, Serialize, Deserialize, Debug, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq)]
 pub enum AutoUpdateMode {
   /// Auto-updating is disabled
   None,
   /// Auto-updating is enabled
-  Version,
+  ///
+  /// Contains the pattern of the commit (e.g. `v%v` or `+-fdroid %v`), if one is specified.
+  Version(Option<String>),
+}
+
+impl AutoUpdateMode {
+  /// The value used in metadata files, e.g. `Version v%v`
+  pub fn name(&self) -> String {
+    match self {
+      Self::None => "None".to_owned(),
+      Self::Version(None) => "Version".to_owned(),
+      Self::Version(Some(pattern)) => format!("Version {pattern}"),
+    }
+  }
+}
+
+impl TryFrom<&str> for AutoUpdateMode {
+  type Error = String;
+
+  fn try_from(name: &str) -> std::result::Result<Self, Self::Error> {
+    match name {
+      "None" => Ok(Self::None),
+      "Version" => Ok(Self::Version(None)),
+      _ => name
+        .strip_prefix("Version ")
+        .map(|pattern| Self::Version(Some(pattern.trim().to_owned())))
+        .ok_or(format!("unknown AutoUpdateMode \"{name}\"")),
+    }
+  }
+}
+
+impl Serialize for AutoUpdateMode {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.name())
+  }
+}
+
+impl<'de> Deserialize<'de> for AutoUpdateMode {
+  fn deserialize<D: serde::Deserializer<'de>>(
+    deserializer: D,
+  ) -> std::result::Result<Self, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    Self::try_from(name.as_str()).map_err(serde::de::Error::custom)
+  }
 }
 
 /// By default, ‘android update’ is used in Ant builds to generate or update the project and all its referenced projects. Specifying androidupdate: no bypasses that. Note that this is useless in builds that don’t use Ant.
@@ -607,8 +881,20 @@ impl Repository {
     // convert data to string
     let file_content = serde_yaml::to_string(metadata)?;
 
+    let old_metadata = self.metadata(package_name).ok();
+
     // write data to file
-    fs::write(meta_file_path, file_content).map_err(Error::from)
+    fs::write(meta_file_path, file_content)?;
+
+    let diff = FieldChange::between(old_metadata.as_ref(), metadata);
+    if !diff.is_empty() {
+      self.emit(RepositoryEvent::MetadataChanged {
+        package: package_name.to_owned(),
+        diff,
+      });
+    }
+
+    Ok(())
   }
 
   /// Creates an empty metadata file (if none exist) and runs `fdroid rewritemeta`
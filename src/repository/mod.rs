@@ -1,20 +1,59 @@
 use std::{fs, path::PathBuf, process::Command};
 
 use crate::error::*;
+use cache::{Cache, CACHE_FILE};
 use log::{debug, error, info, warn};
 
 #[cfg(test)]
 mod tests;
 
 mod app;
+mod archive;
+mod audit;
+mod build;
+mod cache;
+mod checkupdates;
 mod config;
+mod deploy;
+mod import;
+mod lint;
 pub mod metadata;
+mod observer;
 mod paths;
+mod permissions;
+mod policy;
+mod query;
+mod s3;
+mod scanner;
+#[cfg(feature = "serve")]
+mod serve;
+mod share;
+mod srclib;
+mod vercode;
+mod verify;
+mod webhook;
 
 // Re-Export
 pub use app::*;
+pub use archive::*;
+pub use audit::*;
+pub use build::*;
+pub use checkupdates::*;
 pub use config::*;
-pub use paths::*;
+pub use deploy::*;
+pub use import::*;
+pub use lint::*;
+pub use observer::*;
+pub use permissions::*;
+pub use policy::*;
+pub use query::*;
+pub use scanner::*;
+#[cfg(feature = "qr")]
+pub use share::QrFormat;
+pub use srclib::*;
+pub use vercode::*;
+pub use verify::*;
+pub use webhook::*;
 
 /// The main struct of this crate.
 ///
@@ -25,6 +64,16 @@ pub use paths::*;
 pub struct Repository {
   /// absolute path of the /fdroid repository
   path: PathBuf,
+  /// rules every uploaded apk has to follow
+  upload_policy: UploadPolicy,
+  /// receive an event after every change
+  observers: Observers,
+  /// parsed index files and apk info
+  cache: Cache,
+  /// command used by [Repository::build]
+  build_backend: BuildBackend,
+  /// program which runs all other fdroid commands, e.g. `fdroid update`
+  fdroid_program: String,
 }
 
 impl Repository {
@@ -48,7 +97,7 @@ impl Repository {
       return Err(Error::NotADirectory(path));
     }
 
-    let repository = Self { path };
+    let repository = Self::from_path(path);
 
     // check if config.yml exists
     if !(repository.config_path().exists()) {
@@ -59,6 +108,52 @@ impl Repository {
     Ok(repository)
   }
 
+  /// Opens an existing [`Repository`] without ever initializing a new one
+  ///
+  /// # Errors
+  ///
+  /// This function returns an error if:
+  /// - the provided path is not a directory
+  /// - the config file in [`Repository::config_path`] does not exist
+  pub fn open(path: PathBuf) -> Result<Self> {
+    if !path.is_dir() {
+      return Err(Error::NotADirectory(path));
+    }
+
+    let repository = Self::from_path(path);
+
+    if !repository.config_path().is_file() {
+      return Err(Error::NotAFile(repository.config_path()));
+    }
+
+    Ok(repository)
+  }
+
+  /// Creates an instance without checking or initializing the repository
+  fn from_path(path: PathBuf) -> Self {
+    Self {
+      cache: Cache::new(path.join("tmp").join(CACHE_FILE)),
+      path,
+      upload_policy: UploadPolicy::default(),
+      observers: Observers::default(),
+      build_backend: BuildBackend::default(),
+      fdroid_program: "fdroid".to_owned(),
+    }
+  }
+
+  /// Returns the program used to run fdroid commands (`fdroid` by default)
+  pub fn fdroid_program(&self) -> &str {
+    &self.fdroid_program
+  }
+
+  /// Sets the program used to run all further fdroid commands, e.g. the path of a specific
+  /// fdroidserver installation
+  ///
+  /// It is called with the fdroid command (e.g. `update`) and its arguments.
+  pub fn set_fdroid_program(&mut self, program: &str) {
+    self.fdroid_program = program.to_owned();
+  }
+
   /// Initializes a new repository
   ///
   /// # Error
@@ -85,14 +180,20 @@ impl Repository {
     info!("Updating Repository");
 
     self.run("update", &vec!["-c"]).map_err(|_| Error::Update)?;
-    self.run("update", &vec![]).map_err(|_| Error::Update)
+    self.run("update", &vec![]).map_err(|_| Error::Update)?;
+
+    self.emit(RepositoryEvent::IndexRebuilt);
+
+    Ok(())
   }
 
   /// Runs `fdroid publish`
   pub fn publish(&self) -> Result<()> {
     info!("Publishing Changes");
 
-    self.run("publish", &vec![])
+    self
+      .run("publish", &vec![])
+      .map_err(|err| self.publish_failed(None, err))
   }
 
   /// Deletes **all** apps and metadata (but keeps everything else)
@@ -135,36 +236,43 @@ impl Repository {
   }
 
   /// Runs an fdroid command with the specified arguments
+  ///
+  /// Returns [Error::Run] if the command can't be started or exits with a non-zero status
   fn run(&self, command: &str, args: &Vec<&str>) -> Result<()> {
-    info!("Running command: \"fdroid {command}\" with arguemnts: \"{args:#?}\"");
-    let run_result = Command::new("fdroid")
+    let program = &self.fdroid_program;
+    info!("Running command: \"{program} {command}\" with arguemnts: \"{args:#?}\"");
+    let run_result = Command::new(program)
       .arg(command)
       .args(args)
       .current_dir(&self.path)
       .spawn()
-      .map_err(|err| {
+      .inspect_err(|err| {
         debug!("Error spawning run command: {err:#?}");
-        err
       })
       .ok()
       .and_then(|mut process| {
         process
           .wait()
-          .map_err(|err| {
+          .inspect_err(|_| {
             debug!("Error while running process: {process:#?}");
-            err
           })
           .ok()
+      })
+      .filter(|status| {
+        if !status.success() {
+          debug!("\"{program} {command}\" exited with {status}");
+        }
+        status.success()
       });
 
     if run_result.is_none() {
       let error_message =
-        format!("Failed to run command: \"fdroid {command}\" with arguemnts: \"{args:#?}\"");
+        format!("Failed to run command: \"{program} {command}\" with arguemnts: \"{args:#?}\"");
       error!("{}", error_message);
     }
 
     run_result.map(|_| ()).ok_or(Error::Run(
-      format!("fdroid {command} {}", args.join(" "))
+      format!("{program} {command} {}", args.join(" "))
         .trim()
         .to_string(),
     ))
@@ -0,0 +1,176 @@
+//! Extension of Repository used to get notified about changes
+//!
+//! Register a [RepositoryObserver] with [Repository::add_observer] to receive a
+//! [RepositoryEvent] after every successful change (e.g. to send chat notifications or to
+//! invalidate caches).
+
+use std::fmt;
+use std::sync::Arc;
+
+use log::debug;
+use serde::Serialize;
+
+use crate::error::Error;
+use crate::metadata::AppMetadata;
+
+use super::{DeployTarget, Repository};
+
+/// A change of a [Repository]
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(tag = "event")]
+pub enum RepositoryEvent {
+  /// An apk has been added with [Repository::add_app]
+  AppAdded {
+    package: String,
+    version_code: Option<u64>,
+  },
+  /// An apk has been signed with the repository key and added with [Repository::sign_app]
+  AppSigned {
+    package: String,
+    version_code: Option<u64>,
+  },
+  /// An apk has been deleted with [Repository::delete_app]
+  AppDeleted { apk_name: String },
+  /// The metadata of an app has been changed with [Repository::set_metadata]
+  MetadataChanged {
+    package: String,
+    /// all fields whose value changed
+    diff: Vec<FieldChange>,
+  },
+  /// The public configuration has been changed with [Repository::set_config]
+  ConfigChanged,
+  /// The index files have been regenerated by [Repository::update]
+  ///
+  /// Only sent if all `fdroid update` runs exited successfully
+  IndexRebuilt,
+  /// A line of output of [Repository::build]
+  ///
+  /// Builds print many lines, so observers which forward events should usually ignore this
+  /// event. Webhooks only receive it if it is listed in their `events`.
+  BuildLog {
+    package: String,
+    version_code: u64,
+    line: String,
+  },
+  /// [Repository::build] finished, the apk is in the unsigned directory if it succeeded
+  BuildFinished {
+    package: String,
+    version_code: u64,
+    success: bool,
+  },
+  /// [Repository::publish] or deploying to a target failed
+  ///
+  /// For [Repository::publish], this includes `fdroid publish` exiting with a non-zero status
+  PublishFailed {
+    /// the target of [Repository::deploy_to], [None] for [Repository::publish]
+    target: Option<DeployTarget>,
+    /// name of the [Error] variant (see [Error::kind])
+    error: String,
+    message: String,
+  },
+}
+
+/// The old and the new value of a single metadata field
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct FieldChange {
+  pub field: String,
+  /// `null` if the field was not set
+  pub old: serde_json::Value,
+  /// `null` if the field has been removed
+  pub new: serde_json::Value,
+}
+
+impl FieldChange {
+  /// Compares all fields of two metadata files
+  ///
+  /// `old` is [None] if no metadata existed before.
+  pub fn between(old: Option<&AppMetadata>, new: &AppMetadata) -> Vec<Self> {
+    let to_object = |metadata: Option<&AppMetadata>| {
+      metadata
+        .and_then(|metadata| serde_json::to_value(metadata).ok())
+        .and_then(|value| value.as_object().cloned())
+        .unwrap_or_default()
+    };
+
+    let old = to_object(old);
+    let new = to_object(Some(new));
+
+    new
+      .iter()
+      .filter_map(|(field, new_value)| {
+        let old_value = old.get(field).unwrap_or(&serde_json::Value::Null);
+
+        (old_value != new_value).then(|| Self {
+          field: field.clone(),
+          old: old_value.clone(),
+          new: new_value.clone(),
+        })
+      })
+      .collect()
+  }
+}
+
+/// Receives all [RepositoryEvent]s of a [Repository]
+///
+/// Events are delivered synchronously on the thread that made the change, so long running
+/// work (e.g. http requests) should be moved to another thread.
+///
+/// Closures taking a `&RepositoryEvent` implement this trait as well:
+/// ```no_run
+/// # use std::path::PathBuf;
+/// # use std::sync::Arc;
+/// # use fdroid::{Repository, RepositoryEvent};
+/// let mut repository = Repository::new(PathBuf::from("/fdroid")).unwrap();
+/// repository.add_observer(Arc::new(|event: &RepositoryEvent| println!("{event:?}")));
+/// ```
+pub trait RepositoryObserver: Send + Sync {
+  fn notify(&self, event: &RepositoryEvent);
+}
+
+impl<F> RepositoryObserver for F
+where
+  F: Fn(&RepositoryEvent) + Send + Sync,
+{
+  fn notify(&self, event: &RepositoryEvent) {
+    self(event)
+  }
+}
+
+/// All observers registered on a [Repository]
+#[derive(Clone, Default)]
+pub(crate) struct Observers(Vec<Arc<dyn RepositoryObserver>>);
+
+impl fmt::Debug for Observers {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "Observers({})", self.0.len())
+  }
+}
+
+impl Repository {
+  /// Registers an observer which receives all further [RepositoryEvent]s
+  ///
+  /// Clones of the repository created afterwards share the observer.
+  pub fn add_observer(&mut self, observer: Arc<dyn RepositoryObserver>) {
+    self.observers.0.push(observer);
+  }
+
+  /// Sends an event to all observers
+  pub(crate) fn emit(&self, event: RepositoryEvent) {
+    debug!("Emitting {event:?}");
+
+    for observer in &self.observers.0 {
+      observer.notify(&event);
+    }
+  }
+
+  /// Sends [RepositoryEvent::PublishFailed] and returns the error
+  pub(crate) fn publish_failed(&self, target: Option<&DeployTarget>, error: Error) -> Error {
+    self.emit(RepositoryEvent::PublishFailed {
+      target: target.cloned(),
+      error: error.kind().to_owned(),
+      message: error.to_string(),
+    });
+
+    error
+  }
+}
@@ -27,6 +27,25 @@ impl Repository {
     self.path.join("metadata")
   }
 
+  /// get the path of the srclibs directory
+  ///
+  /// See [documentation](https://f-droid.org/en/docs/Build_Metadata_Reference/#build_srclibs)
+  pub fn srclibs_path(&self) -> PathBuf {
+    self.path.join("srclibs")
+  }
+
+  /// get the path of the signature files of an upstream apk, which `fdroid publish` applies
+  /// instead of signing the apk with the repository key
+  ///
+  /// See [Reproducible Builds](https://f-droid.org/en/docs/Reproducible_Builds/)
+  pub fn signatures_path(&self, package_name: &str, version_code: u64) -> PathBuf {
+    self
+      .metadata_path()
+      .join(package_name)
+      .join("signatures")
+      .join(version_code.to_string())
+  }
+
   /// gets the path to the unsigned files
   ///
   /// also creates the directory if it does not already exist
@@ -52,4 +71,39 @@ impl Repository {
   pub fn repo_path(&self) -> PathBuf {
     self.path.join("repo")
   }
+
+  /// returns the path to the directory containing all archived apks
+  ///
+  /// See [archive_older](https://f-droid.org/en/docs/Setup_an_F-Droid_App_Repo/)
+  pub fn archive_path(&self) -> PathBuf {
+    self.path.join("archive")
+  }
+
+  /// returns the path to the directory containing the logs of all builds
+  ///
+  /// See [Repository::build_log_path]
+  pub fn logs_path(&self) -> PathBuf {
+    self.path.join("logs")
+  }
+
+  /// returns the path to the cache of parsed index files and apk info
+  ///
+  /// See [Repository::clear_cache]
+  pub fn cache_path(&self) -> PathBuf {
+    self.path.join("tmp").join(super::cache::CACHE_FILE)
+  }
+
+  /// returns the path to the directory containing undelivered webhook messages
+  ///
+  /// See [WebhookDispatcher](super::WebhookDispatcher)
+  pub fn webhook_queue_path(&self) -> PathBuf {
+    self.path.join("tmp").join("webhooks")
+  }
+
+  /// returns the path to the directory containing the clones of the source repositories
+  ///
+  /// See [Repository::check_updates]
+  pub fn checkupdates_path(&self) -> PathBuf {
+    self.path.join("tmp").join("checkupdates")
+  }
 }
@@ -0,0 +1,213 @@
+//! Extension of Repository used to compare the permissions of different [Package] versions
+//!
+//! See [permissions on Android](https://developer.android.com/guide/topics/permissions/overview)
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::error::{Error, Result};
+
+use super::{Package, Repository};
+
+/// All permissions with the protection level `dangerous`
+///
+/// See [Manifest.permission](https://developer.android.com/reference/android/Manifest.permission)
+pub const DANGEROUS_PERMISSIONS: [&str; 40] = [
+  "android.permission.ACCEPT_HANDOVER",
+  "android.permission.ACCESS_BACKGROUND_LOCATION",
+  "android.permission.ACCESS_COARSE_LOCATION",
+  "android.permission.ACCESS_FINE_LOCATION",
+  "android.permission.ACCESS_MEDIA_LOCATION",
+  "android.permission.ACTIVITY_RECOGNITION",
+  "android.permission.ADD_VOICEMAIL",
+  "android.permission.ANSWER_PHONE_CALLS",
+  "android.permission.BLUETOOTH_ADVERTISE",
+  "android.permission.BLUETOOTH_CONNECT",
+  "android.permission.BLUETOOTH_SCAN",
+  "android.permission.BODY_SENSORS",
+  "android.permission.BODY_SENSORS_BACKGROUND",
+  "android.permission.CALL_PHONE",
+  "android.permission.CAMERA",
+  "android.permission.GET_ACCOUNTS",
+  "android.permission.NEARBY_WIFI_DEVICES",
+  "android.permission.POST_NOTIFICATIONS",
+  "android.permission.PROCESS_OUTGOING_CALLS",
+  "android.permission.READ_CALENDAR",
+  "android.permission.READ_CALL_LOG",
+  "android.permission.READ_CONTACTS",
+  "android.permission.READ_EXTERNAL_STORAGE",
+  "android.permission.READ_MEDIA_AUDIO",
+  "android.permission.READ_MEDIA_IMAGES",
+  "android.permission.READ_MEDIA_VIDEO",
+  "android.permission.READ_MEDIA_VISUAL_USER_SELECTED",
+  "android.permission.READ_PHONE_NUMBERS",
+  "android.permission.READ_PHONE_STATE",
+  "android.permission.READ_SMS",
+  "android.permission.RECEIVE_MMS",
+  "android.permission.RECEIVE_SMS",
+  "android.permission.RECEIVE_WAP_PUSH",
+  "android.permission.RECORD_AUDIO",
+  "android.permission.SEND_SMS",
+  "android.permission.USE_SIP",
+  "android.permission.UWB_RANGING",
+  "android.permission.WRITE_CALENDAR",
+  "android.permission.WRITE_CALL_LOG",
+  "android.permission.WRITE_CONTACTS",
+];
+
+/// Returns true if the permission has the protection level `dangerous`
+pub fn is_dangerous_permission(permission: &str) -> bool {
+  DANGEROUS_PERMISSIONS.contains(&permission)
+}
+
+/// A permission that is requested by both versions, but with a different `maxSdkVersion`
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct MaxSdkChange {
+  pub permission: String,
+  pub from: Option<u32>,
+  pub to: Option<u32>,
+}
+
+/// Difference of the permissions, features and native ABIs between two [Package] versions
+///
+/// Create one by calling [Repository::permission_diff].
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct PermissionDiff {
+  /// permissions (and their max sdk version) only requested by the newer version
+  pub added_permissions: Vec<(String, Option<u32>)>,
+  /// permissions (and their max sdk version) only requested by the older version
+  pub removed_permissions: Vec<(String, Option<u32>)>,
+  /// permissions requested by both versions, but with a different max sdk version
+  pub changed_max_sdk_versions: Vec<MaxSdkChange>,
+  /// hardware/software features only used by the newer version
+  pub added_features: Vec<String>,
+  /// hardware/software features only used by the older version
+  pub removed_features: Vec<String>,
+  /// native ABIs only supported by the newer version
+  pub added_abis: Vec<String>,
+  /// native ABIs only supported by the older version
+  pub removed_abis: Vec<String>,
+}
+
+impl PermissionDiff {
+  /// Compares two packages
+  pub fn between(from: &Package, to: &Package) -> Self {
+    let from_permissions: BTreeMap<_, _> = from.uses_permission.iter().cloned().collect();
+    let to_permissions: BTreeMap<_, _> = to.uses_permission.iter().cloned().collect();
+
+    let mut diff = Self::default();
+
+    for (permission, max_sdk) in &to_permissions {
+      match from_permissions.get(permission) {
+        None => diff.added_permissions.push((permission.clone(), *max_sdk)),
+        Some(from_max_sdk) if from_max_sdk != max_sdk => {
+          diff.changed_max_sdk_versions.push(MaxSdkChange {
+            permission: permission.clone(),
+            from: *from_max_sdk,
+            to: *max_sdk,
+          })
+        }
+        Some(_) => {}
+      }
+    }
+
+    for (permission, max_sdk) in &from_permissions {
+      if !to_permissions.contains_key(permission) {
+        diff
+          .removed_permissions
+          .push((permission.clone(), *max_sdk));
+      }
+    }
+
+    (diff.added_features, diff.removed_features) = difference(&from.features, &to.features);
+    (diff.added_abis, diff.removed_abis) = difference(&from.nativecode, &to.nativecode);
+
+    diff
+  }
+
+  /// Returns true if both versions request the same permissions, features and ABIs
+  pub fn is_empty(&self) -> bool {
+    self == &Self::default()
+  }
+
+  /// Returns all added permissions with the protection level `dangerous`
+  pub fn added_dangerous_permissions(&self) -> Vec<String> {
+    self
+      .added_permissions
+      .iter()
+      .map(|(permission, _)| permission)
+      .filter(|permission| is_dangerous_permission(permission))
+      .cloned()
+      .collect()
+  }
+}
+
+impl Repository {
+  /// Compares the permissions, features and native ABIs of two versions of a package
+  ///
+  /// Both the repository and the archive are searched for the versions.
+  ///
+  /// # Error
+  /// Returns [Error::PackageNotFound] if one of the versions does not exist
+  pub fn permission_diff(
+    &self,
+    package_name: &str,
+    from_version_code: u64,
+    to_version_code: u64,
+  ) -> Result<PermissionDiff> {
+    let versions = self.all_versions(package_name)?;
+
+    let find = |version_code: u64| {
+      versions
+        .iter()
+        .find(|package| package.version_code == Some(version_code))
+        .ok_or(Error::PackageNotFound {
+          package_name: package_name.to_owned(),
+          version_code: Some(version_code),
+        })
+    };
+
+    Ok(PermissionDiff::between(
+      find(from_version_code)?,
+      find(to_version_code)?,
+    ))
+  }
+
+  /// Returns all dangerous permissions that are not requested by the newest existing version
+  /// of the package
+  ///
+  /// If the package does not exist yet, all dangerous permissions are returned.
+  pub(crate) fn introduced_dangerous_permissions(
+    &self,
+    package_name: &str,
+    permissions: &[(String, Option<u32>)],
+  ) -> Result<Vec<String>> {
+    let newest = self.all_versions(package_name)?.into_iter().next();
+
+    Ok(
+      permissions
+        .iter()
+        .map(|(permission, _)| permission)
+        .filter(|permission| is_dangerous_permission(permission))
+        .filter(|permission| {
+          newest.as_ref().is_none_or(|package| {
+            !package
+              .uses_permission
+              .iter()
+              .any(|(existing, _)| existing == *permission)
+          })
+        })
+        .cloned()
+        .collect(),
+    )
+  }
+}
+
+/// Returns the entries only in `to` and the entries only in `from`
+fn difference(from: &[String], to: &[String]) -> (Vec<String>, Vec<String>) {
+  let added = to.iter().filter(|entry| !from.contains(entry)).cloned();
+  let removed = from.iter().filter(|entry| !to.contains(entry)).cloned();
+
+  (added.collect(), removed.collect())
+}
@@ -0,0 +1,214 @@
+//! Extension of Repository used to validate apks before they are uploaded
+
+use std::path::PathBuf;
+
+use log::{debug, warn};
+
+use crate::aapt::*;
+use crate::error::{Error, InvalidFile, PolicyViolation, Result};
+
+use super::{Repository, SignatureKind};
+
+/// Rules every uploaded apk has to follow
+///
+/// The policy is checked by [`Repository::add_app`] and [`Repository::sign_app`].
+/// By default, every rule is disabled.
+///
+/// Set the policy of a repository with [`Repository::set_upload_policy`].
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct UploadPolicy {
+  /// rejects an apk if the same package and version code already exist
+  /// (in the repository or the archive)
+  pub reject_duplicates: bool,
+  /// rejects an apk if its version code is lower than the suggested version code of the app
+  pub reject_downgrades: bool,
+  /// rejects an apk if its package name is not the expected one
+  pub expected_package: Option<String>,
+  /// rejects an apk if it requests dangerous permissions the newest existing version does not
+  /// request (see [DANGEROUS_PERMISSIONS](super::DANGEROUS_PERMISSIONS))
+  pub reject_dangerous_permissions: bool,
+  /// dangerous permissions that have been explicitly approved and are therefore never rejected
+  pub approved_permissions: Vec<String>,
+  /// rejects an apk if it contains a tracker (see [Repository::scan_apk])
+  pub reject_trackers: bool,
+  /// rejects an apk if it contains a proprietary library (see [Repository::scan_apk])
+  pub reject_non_free: bool,
+  /// path to an updated [SignatureDatabase](super::SignatureDatabase),
+  /// the bundled one is used if it is not set
+  pub signature_database: Option<PathBuf>,
+}
+
+impl UploadPolicy {
+  /// Returns true if no rule is enabled
+  fn is_permissive(&self) -> bool {
+    !self.reject_duplicates
+      && !self.reject_downgrades
+      && self.expected_package.is_none()
+      && !self.reject_dangerous_permissions
+      && !self.reject_trackers
+      && !self.reject_non_free
+  }
+}
+
+impl Repository {
+  /// Returns the current [UploadPolicy]
+  pub fn upload_policy(&self) -> &UploadPolicy {
+    &self.upload_policy
+  }
+
+  /// Sets the [UploadPolicy] used for all further uploads
+  pub fn set_upload_policy(&mut self, upload_policy: UploadPolicy) {
+    self.upload_policy = upload_policy;
+  }
+
+  /// Checks if an apk file can be uploaded according to the [UploadPolicy]
+  ///
+  /// # Error
+  /// Returns [`Error::UploadPolicy`] if a rule is violated or an error if the apk can't be parsed
+  pub fn check_upload_policy(&self, file_path: &PathBuf) -> Result<()> {
+    if self.upload_policy.is_permissive() {
+      return Ok(());
+    }
+
+    let apk_metadata = self.apk_info(file_path)?;
+
+    let version_code = get_version_code(&apk_metadata).ok_or(Error::InvalidFile(
+      InvalidFile::with_reason(file_path.clone(), "Version Code not found!"),
+    ))?;
+    let package_name = get_name(&apk_metadata).ok_or(Error::InvalidFile(
+      InvalidFile::with_reason(file_path.clone(), "Name not found!"),
+    ))?;
+
+    self.check_upload(&package_name, version_code.into())?;
+
+    self.check_permissions(
+      &package_name,
+      version_code.into(),
+      &get_permissions(&apk_metadata),
+    )?;
+
+    self.check_libraries(file_path, &package_name, version_code.into())
+  }
+
+  /// Checks a package name and version code against the [UploadPolicy]
+  pub(crate) fn check_upload(&self, package_name: &str, version_code: u64) -> Result<()> {
+    debug!("Checking upload policy for {package_name} ({version_code})");
+    let policy = &self.upload_policy;
+
+    if let Some(expected) = &policy.expected_package {
+      if expected != package_name {
+        return Err(violation(PolicyViolation::PackageMismatch {
+          expected: expected.clone(),
+          actual: package_name.to_owned(),
+        }));
+      }
+    }
+
+    if policy.reject_duplicates {
+      let existing = self
+        .all_versions(package_name)?
+        .into_iter()
+        .find(|package| package.version_code == Some(version_code));
+
+      if let Some(existing) = existing {
+        return Err(violation(PolicyViolation::Duplicate {
+          package_name: package_name.to_owned(),
+          version_code,
+          existing_apk: existing.apk_name,
+        }));
+      }
+    }
+
+    if policy.reject_downgrades {
+      let suggested_version_code = self
+        .apps()?
+        .into_iter()
+        .find(|app| app.package_name == package_name)
+        .and_then(|app| app.suggested_version_code.parse::<u64>().ok());
+
+      if let Some(suggested_version_code) = suggested_version_code {
+        if version_code < suggested_version_code {
+          return Err(violation(PolicyViolation::Downgrade {
+            package_name: package_name.to_owned(),
+            version_code,
+            suggested_version_code,
+          }));
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Checks the requested permissions of an apk against the [UploadPolicy]
+  pub(crate) fn check_permissions(
+    &self,
+    package_name: &str,
+    version_code: u64,
+    permissions: &[(String, Option<u32>)],
+  ) -> Result<()> {
+    let policy = &self.upload_policy;
+
+    if !policy.reject_dangerous_permissions {
+      return Ok(());
+    }
+
+    let unapproved: Vec<String> = self
+      .introduced_dangerous_permissions(package_name, permissions)?
+      .into_iter()
+      .filter(|permission| !policy.approved_permissions.contains(permission))
+      .collect();
+
+    if unapproved.is_empty() {
+      Ok(())
+    } else {
+      Err(violation(PolicyViolation::DangerousPermissions {
+        package_name: package_name.to_owned(),
+        version_code,
+        permissions: unapproved,
+      }))
+    }
+  }
+
+  /// Scans an apk for trackers and non-free libraries and checks them against the [UploadPolicy]
+  pub(crate) fn check_libraries(
+    &self,
+    file_path: &PathBuf,
+    package_name: &str,
+    version_code: u64,
+  ) -> Result<()> {
+    let policy = &self.upload_policy;
+
+    if !policy.reject_trackers && !policy.reject_non_free {
+      return Ok(());
+    }
+
+    let report = self.scan_apk(file_path)?;
+
+    let rejected: Vec<String> = report
+      .detections
+      .into_iter()
+      .filter(|detection| match detection.kind {
+        SignatureKind::Tracker => policy.reject_trackers,
+        SignatureKind::NonFree => policy.reject_non_free,
+      })
+      .map(|detection| detection.name)
+      .collect();
+
+    if rejected.is_empty() {
+      Ok(())
+    } else {
+      Err(violation(PolicyViolation::Libraries {
+        package_name: package_name.to_owned(),
+        version_code,
+        libraries: rejected,
+      }))
+    }
+  }
+}
+
+/// Logs and wraps a [PolicyViolation]
+fn violation(violation: PolicyViolation) -> Error {
+  warn!("Upload rejected: {violation}");
+  Error::UploadPolicy(violation)
+}
@@ -0,0 +1,292 @@
+//! Extension of Repository used to search the app catalogue
+//!
+//! ```no_run
+//! # use std::path::PathBuf;
+//! # use fdroid::{AppQuery, AppSort, Repository, SortOrder};
+//! # use fdroid::metadata::{AntiFeature, Category};
+//! let repository = Repository::new(PathBuf::from("/fdroid")).unwrap();
+//!
+//! let query = AppQuery::new()
+//!   .category(Category::Internet)
+//!   .without_anti_feature(AntiFeature::Tracking)
+//!   .text("browser")
+//!   .sort(AppSort::LastUpdated, SortOrder::Descending)
+//!   .limit(20);
+//!
+//! let page = repository.query(&query).unwrap();
+//! println!("{} of {} apps", page.apps.len(), page.total);
+//! ```
+
+use std::ops::{Bound, RangeBounds};
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::metadata::{AntiFeature, Category};
+
+use super::{App, Package, Repository};
+
+/// Field the results of an [AppQuery] are sorted by
+#[derive(Debug, Clone, Copy, Default, Serialize, Eq, PartialEq)]
+pub enum AppSort {
+  /// case-insensitive by name
+  #[default]
+  Name,
+  PackageName,
+  Added,
+  LastUpdated,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Eq, PartialEq)]
+pub enum SortOrder {
+  #[default]
+  Ascending,
+  Descending,
+}
+
+/// Filters, sorting and pagination for [Repository::query]
+///
+/// All filters have to match. If a filter is set multiple times (e.g. two categories),
+/// any of the values has to match.
+#[derive(Debug, Clone, Default)]
+pub struct AppQuery {
+  categories: Vec<Category>,
+  licenses: Vec<String>,
+  anti_features: Vec<AntiFeature>,
+  excluded_anti_features: Vec<AntiFeature>,
+  permissions: Vec<String>,
+  sdk: Option<u32>,
+  abis: Vec<String>,
+  added: Option<(Bound<i64>, Bound<i64>)>,
+  last_updated: Option<(Bound<i64>, Bound<i64>)>,
+  text: Option<String>,
+  sort: AppSort,
+  order: SortOrder,
+  offset: usize,
+  limit: Option<usize>,
+}
+
+/// A single page of the results of [Repository::query]
+#[derive(Clone, Serialize)]
+pub struct AppPage {
+  pub apps: Vec<App>,
+  /// number of all matching apps (ignoring the pagination)
+  pub total: usize,
+  pub offset: usize,
+  pub limit: Option<usize>,
+}
+
+impl AppQuery {
+  /// Creates a query matching all apps, sorted by name
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Only apps in this category (predefined or [Category::Custom])
+  pub fn category(mut self, category: Category) -> Self {
+    self.categories.push(category);
+    self
+  }
+
+  /// Only apps with this license (case-insensitive), e.g. `GPL-3.0-only`
+  pub fn license(mut self, license: &str) -> Self {
+    self.licenses.push(license.to_lowercase());
+    self
+  }
+
+  /// Only apps with this anti-feature
+  pub fn anti_feature(mut self, anti_feature: AntiFeature) -> Self {
+    self.anti_features.push(anti_feature);
+    self
+  }
+
+  /// Only apps without this anti-feature
+  pub fn without_anti_feature(mut self, anti_feature: AntiFeature) -> Self {
+    self.excluded_anti_features.push(anti_feature);
+    self
+  }
+
+  /// Only apps whose suggested version requests this permission,
+  /// either the full name (`android.permission.CAMERA`) or the short one (`CAMERA`)
+  pub fn permission(mut self, permission: &str) -> Self {
+    self.permissions.push(permission.to_owned());
+    self
+  }
+
+  /// Only apps with a version which can be installed on a device with this sdk level
+  /// (`minSdkVersion <= sdk <= maxSdkVersion`)
+  pub fn compatible_with_sdk(mut self, sdk: u32) -> Self {
+    self.sdk = Some(sdk);
+    self
+  }
+
+  /// Only apps with a version supporting this native ABI (e.g. `arm64-v8a`),
+  /// apps without native code support every ABI
+  pub fn abi(mut self, abi: &str) -> Self {
+    self.abis.push(abi.to_owned());
+    self
+  }
+
+  /// Only apps added within this range of unix timestamps (in milliseconds)
+  pub fn added(mut self, range: impl RangeBounds<i64>) -> Self {
+    self.added = Some((range.start_bound().cloned(), range.end_bound().cloned()));
+    self
+  }
+
+  /// Only apps last updated within this range of unix timestamps (in milliseconds)
+  pub fn last_updated(mut self, range: impl RangeBounds<i64>) -> Self {
+    self.last_updated = Some((range.start_bound().cloned(), range.end_bound().cloned()));
+    self
+  }
+
+  /// Only apps whose name, summary or description contain all words of `text`
+  /// (case-insensitive)
+  pub fn text(mut self, text: &str) -> Self {
+    self.text = Some(text.to_lowercase());
+    self
+  }
+
+  pub fn sort(mut self, sort: AppSort, order: SortOrder) -> Self {
+    self.sort = sort;
+    self.order = order;
+    self
+  }
+
+  /// Skips the first `offset` matching apps
+  pub fn offset(mut self, offset: usize) -> Self {
+    self.offset = offset;
+    self
+  }
+
+  /// Returns at most `limit` apps
+  pub fn limit(mut self, limit: usize) -> Self {
+    self.limit = Some(limit);
+    self
+  }
+
+  /// Returns true if the app matches all filters
+  pub fn matches(&self, app: &App) -> bool {
+    let in_range = |range: &Option<(Bound<i64>, Bound<i64>)>, timestamp: i64| {
+      range.is_none_or(|range| range.contains(&timestamp))
+    };
+
+    any_or_empty(&self.categories, |category| {
+      app.categories.contains(category)
+    }) && any_or_empty(&self.licenses, |license| {
+      &app.license.to_lowercase() == license
+    }) && any_or_empty(&self.anti_features, |anti_feature| {
+      app.anti_features.contains(anti_feature)
+    }) && !self
+      .excluded_anti_features
+      .iter()
+      .any(|anti_feature| app.anti_features.contains(anti_feature))
+      && any_or_empty(&self.permissions, |permission| {
+        suggested_package(app).is_some_and(|package| {
+          package.uses_permission.iter().any(|(name, _)| {
+            name == permission || name.rsplit('.').next() == Some(permission.as_str())
+          })
+        })
+      })
+      && self.sdk.is_none_or(|sdk| {
+        app.packages.iter().any(|package| {
+          package.min_sdk_version.unwrap_or(1) <= sdk
+            && package.max_sdk_version.is_none_or(|max| sdk <= max)
+        })
+      })
+      && any_or_empty(&self.abis, |abi| {
+        app
+          .packages
+          .iter()
+          .any(|package| package.nativecode.is_empty() || package.nativecode.contains(abi))
+      })
+      && in_range(&self.added, app.added)
+      && in_range(&self.last_updated, app.last_updated)
+      && self
+        .text
+        .as_deref()
+        .is_none_or(|text| matches_text(app, text))
+  }
+
+  /// Filters, sorts and paginates a list of apps
+  pub fn apply(&self, apps: Vec<App>) -> AppPage {
+    let mut apps: Vec<App> = apps.into_iter().filter(|app| self.matches(app)).collect();
+
+    apps.sort_by(|a, b| {
+      let ordering = match self.sort {
+        AppSort::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+        AppSort::PackageName => a.package_name.cmp(&b.package_name),
+        AppSort::Added => a.added.cmp(&b.added),
+        AppSort::LastUpdated => a.last_updated.cmp(&b.last_updated),
+      }
+      // stable order for pagination
+      .then_with(|| a.package_name.cmp(&b.package_name));
+
+      match self.order {
+        SortOrder::Ascending => ordering,
+        SortOrder::Descending => ordering.reverse(),
+      }
+    });
+
+    let total = apps.len();
+    let apps = apps
+      .into_iter()
+      .skip(self.offset)
+      .take(self.limit.unwrap_or(usize::MAX))
+      .collect();
+
+    AppPage {
+      apps,
+      total,
+      offset: self.offset,
+      limit: self.limit,
+    }
+  }
+}
+
+impl Repository {
+  /// Searches the apps of the repository (not the archive)
+  ///
+  /// # Error
+  /// Returns an error if the index can't be read
+  pub fn query(&self, query: &AppQuery) -> Result<AppPage> {
+    Ok(query.apply(self.apps()?))
+  }
+}
+
+/// Returns the package of the suggested version code (or the newest package)
+fn suggested_package(app: &App) -> Option<&Package> {
+  app
+    .packages
+    .iter()
+    .find(|package| {
+      package
+        .version_code
+        .is_some_and(|version_code| version_code.to_string() == app.suggested_version_code)
+    })
+    .or_else(|| {
+      app
+        .packages
+        .iter()
+        .max_by_key(|package| package.version_code)
+    })
+}
+
+/// Returns true if `values` is empty or any value matches
+fn any_or_empty<T>(values: &[T], matches: impl Fn(&T) -> bool) -> bool {
+  values.is_empty() || values.iter().any(matches)
+}
+
+fn matches_text(app: &App, text: &str) -> bool {
+  let haystack = [
+    Some(app.name.as_str()),
+    app.summary.as_deref(),
+    app.description.as_deref(),
+  ]
+  .into_iter()
+  .flatten()
+  .collect::<Vec<_>>()
+  .join("\n")
+  .to_lowercase();
+
+  text.split_whitespace().all(|word| haystack.contains(word))
+}
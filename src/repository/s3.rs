@@ -0,0 +1,391 @@
+//! Deploy target for S3 compatible object storage (AWS S3, MinIO, etc.)
+//!
+//! Requests are signed with [Signature Version 4](https://docs.aws.amazon.com/AmazonS3/latest/API/sig-v4-authenticating-requests.html)
+//! and use path-style urls (`<endpoint>/<bucket>/<key>`), which are supported by all providers.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use log::{debug, warn};
+use md5::Md5;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+use crate::integrity::file_sha256;
+
+use super::deploy::{is_index_file, list_files, DeployReport};
+
+/// Region used if `awsregion` is not configured
+pub(crate) const DEFAULT_REGION: &str = "us-east-1";
+
+/// Objects inside of the bucket are stored below this prefix, same as `fdroid deploy`
+const BUCKET_PREFIX: &str = "fdroid";
+
+/// Index files change with every update and must not be cached for long
+const INDEX_CACHE_CONTROL: &str = "public, max-age=60";
+/// Apks never change (the version code is part of the name)
+const APK_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+/// Icons, screenshots, etc.
+const DEFAULT_CACHE_CONTROL: &str = "public, max-age=86400";
+
+/// Payload hash of requests without a body
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Credentials of an S3 account
+#[derive(Clone)]
+pub(crate) struct Credentials {
+  pub(crate) access_key_id: String,
+  pub(crate) secret_key: String,
+  pub(crate) region: String,
+}
+
+/// Minimal S3 client, only supports the operations needed for deploying
+pub(crate) struct S3Client {
+  /// e.g. `https://s3.us-east-1.amazonaws.com`, without trailing slash
+  endpoint: String,
+  bucket: String,
+  credentials: Credentials,
+}
+
+impl S3Client {
+  pub(crate) fn new(endpoint: &str, bucket: &str, credentials: Credentials) -> Self {
+    Self {
+      endpoint: endpoint.trim_end_matches('/').to_owned(),
+      bucket: bucket.to_owned(),
+      credentials,
+    }
+  }
+
+  /// Returns the key and the ETag of all objects starting with `prefix`
+  fn list_objects(&self, prefix: &str) -> Result<BTreeMap<String, String>> {
+    let object_regex =
+      Regex::new(r"(?s)<Contents>.*?<Key>(.*?)</Key>.*?<ETag>(.*?)</ETag>.*?</Contents>")
+        .expect("invalid regex");
+    let token_regex =
+      Regex::new(r"<NextContinuationToken>(.*?)</NextContinuationToken>").expect("invalid regex");
+
+    let mut objects = BTreeMap::new();
+    let mut continuation_token = None;
+
+    loop {
+      let mut query = vec![
+        ("list-type".to_owned(), "2".to_owned()),
+        ("prefix".to_owned(), prefix.to_owned()),
+      ];
+      if let Some(token) = continuation_token.take() {
+        query.push(("continuation-token".to_owned(), token));
+      }
+
+      let body = self
+        .request("GET", "", &query, EMPTY_SHA256)
+        .call()
+        .map_err(http_error)?
+        .into_string()?;
+
+      for captures in object_regex.captures_iter(&body) {
+        objects.insert(
+          unescape_xml(&captures[1]),
+          unescape_xml(&captures[2]).trim_matches('"').to_owned(),
+        );
+      }
+
+      match token_regex.captures(&body) {
+        Some(captures) if body.contains("<IsTruncated>true</IsTruncated>") => {
+          continuation_token = Some(unescape_xml(&captures[1]));
+        }
+        _ => return Ok(objects),
+      }
+    }
+  }
+
+  /// Uploads a file
+  fn put_object(&self, key: &str, file_path: &PathBuf, cache_control: &str) -> Result<()> {
+    let payload_hash = file_sha256(file_path)?;
+    let size = file_path.metadata()?.len();
+
+    self
+      .request("PUT", key, &[], &payload_hash)
+      .set("Content-Type", content_type(file_path))
+      .set("Cache-Control", cache_control)
+      .set("Content-Length", &size.to_string())
+      .send(File::open(file_path)?)
+      .map_err(http_error)?;
+
+    Ok(())
+  }
+
+  fn delete_object(&self, key: &str) -> Result<()> {
+    self
+      .request("DELETE", key, &[], EMPTY_SHA256)
+      .call()
+      .map_err(http_error)?;
+
+    Ok(())
+  }
+
+  /// Creates a signed request to an object (or to the bucket if the key is empty)
+  fn request(
+    &self,
+    method: &str,
+    key: &str,
+    query: &[(String, String)],
+    payload_hash: &str,
+  ) -> ureq::Request {
+    let path = if key.is_empty() {
+      format!("/{}", self.bucket)
+    } else {
+      format!("/{}/{}", self.bucket, key)
+    };
+    let host = self
+      .endpoint
+      .split("://")
+      .last()
+      .unwrap_or_default()
+      .split('/')
+      .next()
+      .unwrap_or_default();
+
+    let amz_date = amz_date(SystemTime::now());
+    let authorization = authorization(
+      &self.credentials,
+      method,
+      host,
+      &path,
+      query,
+      payload_hash,
+      &amz_date,
+    );
+
+    let mut url = format!("{}{}", self.endpoint, uri_encode(&path, false));
+    if !query.is_empty() {
+      url.push('?');
+      url.push_str(&canonical_query(query));
+    }
+
+    debug!("{method} {url}");
+
+    ureq::request(method, &url)
+      .set("x-amz-date", &amz_date)
+      .set("x-amz-content-sha256", payload_hash)
+      .set("Authorization", &authorization)
+  }
+}
+
+/// Converts a failed request into an [Error::Http] (the message contains the url)
+fn http_error(err: ureq::Error) -> Error {
+  Error::Http(err.to_string())
+}
+
+/// Syncs a directory to `<bucket>/fdroid/<section>/`
+///
+/// Files are compared by their MD5 hash, which S3 returns as ETag for single part uploads.
+pub(super) fn sync_s3(client: &S3Client, source: &Path, section: &Path) -> Result<DeployReport> {
+  let mut report = DeployReport::default();
+
+  let prefix = format!("{BUCKET_PREFIX}/{}/", section.display());
+  let mut existing = client.list_objects(&prefix)?;
+
+  let source_files = list_files(source)?;
+  let (index_files, other_files): (Vec<_>, Vec<_>) =
+    source_files.iter().partition(|file| is_index_file(file));
+
+  for file in other_files.into_iter().chain(index_files) {
+    let source_file = source.join(file);
+    let key = format!("{prefix}{}", object_key(file));
+
+    if let Some(etag) = existing.remove(&key) {
+      if etag.eq_ignore_ascii_case(&file_md5(&source_file)?) {
+        continue;
+      }
+    }
+
+    debug!("Uploading {key}");
+    client.put_object(&key, &source_file, cache_control(file))?;
+    report.uploaded.push(file.clone());
+  }
+
+  // all remaining objects don't exist in the repository anymore
+  for key in existing.into_keys() {
+    warn!("Deleting {key} from bucket");
+    client.delete_object(&key)?;
+    report
+      .deleted
+      .push(PathBuf::from(key.trim_start_matches(&prefix)));
+  }
+
+  Ok(report)
+}
+
+/// Returns the `Cache-Control` header of a file
+fn cache_control(relative_path: &Path) -> &'static str {
+  if is_index_file(relative_path) {
+    INDEX_CACHE_CONTROL
+  } else if relative_path
+    .extension()
+    .is_some_and(|extension| extension == "apk")
+  {
+    APK_CACHE_CONTROL
+  } else {
+    DEFAULT_CACHE_CONTROL
+  }
+}
+
+/// Returns the `Content-Type` header of a file, based on its extension
+pub(super) fn content_type(file_path: &Path) -> &'static str {
+  let extension = file_path
+    .extension()
+    .map(|extension| extension.to_string_lossy().to_lowercase())
+    .unwrap_or_default();
+
+  match extension.as_str() {
+    "apk" => "application/vnd.android.package-archive",
+    "jar" => "application/java-archive",
+    "json" => "application/json",
+    "xml" => "application/xml",
+    "html" => "text/html; charset=utf-8",
+    "css" => "text/css",
+    "js" => "text/javascript",
+    "txt" => "text/plain; charset=utf-8",
+    "png" => "image/png",
+    "jpg" | "jpeg" => "image/jpeg",
+    "webp" => "image/webp",
+    "svg" => "image/svg+xml",
+    "asc" => "application/pgp-signature",
+    _ => "application/octet-stream",
+  }
+}
+
+/// Converts a relative path into the part of an object key (always uses `/`)
+fn object_key(relative_path: &Path) -> String {
+  relative_path
+    .components()
+    .map(|component| component.as_os_str().to_string_lossy())
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+fn file_md5(file_path: &PathBuf) -> Result<String> {
+  let mut hasher = Md5::new();
+  io::copy(&mut File::open(file_path)?, &mut hasher)?;
+
+  Ok(hex::encode(hasher.finalize()))
+}
+
+fn unescape_xml(value: &str) -> String {
+  value
+    .replace("&quot;", "\"")
+    .replace("&apos;", "'")
+    .replace("&lt;", "<")
+    .replace("&gt;", ">")
+    .replace("&amp;", "&")
+}
+
+/// Returns the value of the `Authorization` header of a request
+///
+/// Signs the headers `host`, `x-amz-content-sha256` and `x-amz-date`.
+pub(crate) fn authorization(
+  credentials: &Credentials,
+  method: &str,
+  host: &str,
+  path: &str,
+  query: &[(String, String)],
+  payload_hash: &str,
+  amz_date: &str,
+) -> String {
+  let date = &amz_date[..8];
+  let scope = format!("{date}/{}/s3/aws4_request", credentials.region);
+  let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+
+  let canonical_request = format!(
+    "{method}\n{}\n{}\nhost:{host}\nx-amz-content-sha256:{payload_hash}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{payload_hash}",
+    uri_encode(path, false),
+    canonical_query(query),
+  );
+
+  let string_to_sign = format!(
+    "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+    hex::encode(Sha256::digest(canonical_request))
+  );
+
+  let signing_key = [date, credentials.region.as_str(), "s3", "aws4_request"]
+    .iter()
+    .fold(
+      format!("AWS4{}", credentials.secret_key).into_bytes(),
+      |key, part| hmac_sha256(&key, part.as_bytes()),
+    );
+
+  format!(
+    "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={}",
+    credentials.access_key_id,
+    hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()))
+  )
+}
+
+pub(super) fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+  let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+  mac.update(data);
+  mac.finalize().into_bytes().to_vec()
+}
+
+/// Sorted and encoded query string
+fn canonical_query(query: &[(String, String)]) -> String {
+  let mut pairs: Vec<String> = query
+    .iter()
+    .map(|(key, value)| format!("{}={}", uri_encode(key, true), uri_encode(value, true)))
+    .collect();
+  pairs.sort();
+
+  pairs.join("&")
+}
+
+/// Percent-encodes everything except unreserved characters (and `/` if it is a path)
+fn uri_encode(value: &str, encode_slash: bool) -> String {
+  value
+    .bytes()
+    .map(|byte| match byte {
+      b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+        (byte as char).to_string()
+      }
+      b'/' if !encode_slash => "/".to_owned(),
+      _ => format!("%{byte:02X}"),
+    })
+    .collect()
+}
+
+/// Formats a time as `YYYYMMDD'T'HHMMSS'Z'`
+pub(crate) fn amz_date(time: SystemTime) -> String {
+  let seconds = time
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or_default();
+
+  // see http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+  let days = (seconds / 86400) as i64 + 719468;
+  let era = days.div_euclid(146097);
+  let day_of_era = days.rem_euclid(146097);
+  let year_of_era =
+    (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month_index = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+  let month = if month_index < 10 {
+    month_index + 3
+  } else {
+    month_index - 9
+  };
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+  let seconds_of_day = seconds % 86400;
+
+  format!(
+    "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+    seconds_of_day / 3600,
+    seconds_of_day % 3600 / 60,
+    seconds_of_day % 60
+  )
+}
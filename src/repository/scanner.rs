@@ -0,0 +1,316 @@
+//! Offline scanner for trackers and non-free libraries inside of apks
+//!
+//! Works like [Exodus Privacy](https://reports.exodus-privacy.eu.org/): the class names of all
+//! dex files and the paths of all native libraries are matched against a [SignatureDatabase].
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+
+use log::{debug, info};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use zip::ZipArchive;
+
+use crate::error::{Error, InvalidFile, Result};
+use crate::metadata::{AntiFeature, AppMetadata};
+
+use super::Repository;
+
+/// Database bundled with this crate
+const BUNDLED_DATABASE: &str = include_str!("trackers.json");
+
+/// Maximum number of matching class names stored per [Detection]
+const MAX_EVIDENCE: usize = 5;
+
+/// Type of a [Signature]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, Ord, PartialOrd, Eq, PartialEq)]
+pub enum SignatureKind {
+  /// Collects data about the user, results in [AntiFeature::Tracking]
+  #[default]
+  Tracker,
+  /// Proprietary library, results in [AntiFeature::NonFreeDep]
+  NonFree,
+}
+
+/// A single entry of the [SignatureDatabase]
+///
+/// Uses the same fields as the [Exodus](https://reports.exodus-privacy.eu.org/api/trackers) api
+/// and extends them with `kind` and `library_signature`.
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct Signature {
+  pub name: String,
+  /// regex matched against the class names (e.g. `com.google.firebase.analytics.`)
+  pub code_signature: String,
+  /// regex matched against the hosts the library connects to (only informative)
+  #[serde(default)]
+  pub network_signature: String,
+  /// regex matched against the paths of the native libraries (e.g. `libgmm-jni.so`)
+  #[serde(default)]
+  pub library_signature: String,
+  #[serde(default)]
+  pub website: String,
+  #[serde(default)]
+  pub kind: SignatureKind,
+}
+
+/// Collection of [Signature]s, in the same format as the Exodus tracker list
+///
+/// Use [SignatureDatabase::bundled] for the database shipped with this crate or load an updated
+/// one with [SignatureDatabase::from_file].
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct SignatureDatabase {
+  /// all signatures, by their id
+  pub trackers: BTreeMap<String, Signature>,
+}
+
+impl SignatureDatabase {
+  /// Returns the database bundled with this crate
+  pub fn bundled() -> Self {
+    Self::from_json(BUNDLED_DATABASE).expect("bundled tracker database is invalid")
+  }
+
+  /// Parses a database from a json string
+  pub fn from_json(json: &str) -> Result<Self> {
+    serde_json::from_str(json).map_err(|err| Error::JsonConvert(err.to_string()))
+  }
+
+  /// Reads a database from a json file
+  pub fn from_file(file_path: &PathBuf) -> Result<Self> {
+    Self::from_json(&fs::read_to_string(file_path)?)
+  }
+
+  /// Scans an apk for all signatures of this database
+  ///
+  /// # Error
+  /// Returns an error if the apk can't be read or a signature is not a valid regex
+  pub fn scan(&self, apk_path: &PathBuf) -> Result<ScanReport> {
+    info!("Scanning {apk_path:?} for trackers");
+
+    let contents = ApkContents::read(apk_path)?;
+    let mut detections = vec![];
+
+    for (id, signature) in &self.trackers {
+      let mut evidence = vec![];
+
+      for (pattern, candidates) in [
+        (&signature.code_signature, &contents.class_names),
+        (&signature.library_signature, &contents.libraries),
+      ] {
+        if pattern.is_empty() {
+          continue;
+        }
+
+        let regex = Regex::new(pattern)
+          .map_err(|err| Error::JsonConvert(format!("Invalid signature \"{id}\": {err}")))?;
+
+        evidence.extend(
+          candidates
+            .iter()
+            .filter(|candidate| regex.is_match(candidate))
+            .take(MAX_EVIDENCE)
+            .cloned(),
+        );
+      }
+
+      if !evidence.is_empty() {
+        debug!("Found {} in {apk_path:?}", signature.name);
+
+        detections.push(Detection {
+          id: id.clone(),
+          name: signature.name.clone(),
+          kind: signature.kind,
+          evidence,
+        });
+      }
+    }
+
+    Ok(ScanReport { detections })
+  }
+}
+
+/// A [Signature] that has been found inside of an apk
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct Detection {
+  /// id of the signature inside of the [SignatureDatabase]
+  pub id: String,
+  pub name: String,
+  pub kind: SignatureKind,
+  /// some of the class names or library paths that matched
+  pub evidence: Vec<String>,
+}
+
+/// Result of [SignatureDatabase::scan]
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct ScanReport {
+  pub detections: Vec<Detection>,
+}
+
+impl ScanReport {
+  /// Returns all detected trackers
+  pub fn trackers(&self) -> Vec<&Detection> {
+    self.of_kind(SignatureKind::Tracker)
+  }
+
+  /// Returns all detected proprietary libraries
+  pub fn non_free(&self) -> Vec<&Detection> {
+    self.of_kind(SignatureKind::NonFree)
+  }
+
+  /// Returns the anti-features the app should be marked with
+  pub fn suggested_anti_features(&self) -> Vec<AntiFeature> {
+    let mut anti_features = vec![];
+
+    if !self.trackers().is_empty() {
+      anti_features.push(AntiFeature::Tracking);
+    }
+    if !self.non_free().is_empty() {
+      anti_features.push(AntiFeature::NonFreeDep);
+    }
+
+    anti_features
+  }
+
+  /// Adds all suggested anti-features to [AppMetadata::AntiFeatures]
+  ///
+  /// Existing anti-features and their reasons are never removed.
+  /// Returns true if the metadata has been changed.
+  pub fn apply_anti_features(&self, metadata: &mut AppMetadata) -> bool {
+    let missing: Vec<AntiFeature> = self
+      .suggested_anti_features()
+      .into_iter()
+      .filter(|suggested| {
+        !metadata
+          .AntiFeatures
+          .as_ref()
+          .is_some_and(|existing| existing.contains_key(suggested))
+      })
+      .collect();
+
+    if missing.is_empty() {
+      return false;
+    }
+
+    let anti_features = metadata.AntiFeatures.get_or_insert_with(BTreeMap::new);
+    for anti_feature in missing {
+      anti_features.insert(anti_feature, BTreeMap::new());
+    }
+    true
+  }
+
+  fn of_kind(&self, kind: SignatureKind) -> Vec<&Detection> {
+    self
+      .detections
+      .iter()
+      .filter(|detection| detection.kind == kind)
+      .collect()
+  }
+}
+
+/// Class names and native libraries of an apk
+struct ApkContents {
+  class_names: Vec<String>,
+  libraries: Vec<String>,
+}
+
+impl ApkContents {
+  fn read(apk_path: &PathBuf) -> Result<Self> {
+    let invalid =
+      |reason: String| Error::InvalidFile(InvalidFile::with_reason(apk_path.clone(), &reason));
+
+    if !apk_path.is_file() {
+      return Err(Error::NotAFile(apk_path.clone()));
+    }
+
+    let mut archive =
+      ZipArchive::new(File::open(apk_path)?).map_err(|err| invalid(err.to_string()))?;
+
+    let mut class_names = vec![];
+    let mut libraries = vec![];
+
+    for index in 0..archive.len() {
+      let mut entry = archive
+        .by_index(index)
+        .map_err(|err| invalid(err.to_string()))?;
+      let name = entry.name().to_owned();
+
+      if name.starts_with("lib/") && name.ends_with(".so") {
+        libraries.push(name);
+      } else if name.starts_with("classes") && name.ends_with(".dex") && !name.contains('/') {
+        let mut dex = vec![];
+        entry.read_to_end(&mut dex)?;
+
+        class_names
+          .extend(dex_class_names(&dex).ok_or(invalid(format!("{name} is not a valid dex file")))?);
+      }
+    }
+
+    Ok(Self {
+      class_names,
+      libraries,
+    })
+  }
+}
+
+/// Reads the names of all types (classes) referenced by a dex file
+///
+/// See [dex format](https://source.android.com/docs/core/runtime/dex-format)
+///
+/// Returns [None] if the file is not a valid dex file
+pub(crate) fn dex_class_names(dex: &[u8]) -> Option<Vec<String>> {
+  if !dex.starts_with(b"dex\n") {
+    return None;
+  }
+
+  let read_u32 = |offset: usize| -> Option<usize> {
+    let bytes = dex.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+  };
+
+  let string_ids_off = read_u32(0x3C)?;
+  let type_ids_size = read_u32(0x40)?;
+  let type_ids_off = read_u32(0x44)?;
+
+  let mut class_names = Vec::with_capacity(type_ids_size);
+
+  for type_index in 0..type_ids_size {
+    let descriptor_index = read_u32(type_ids_off + type_index * 4)?;
+    let mut offset = read_u32(string_ids_off + descriptor_index * 4)?;
+
+    // skip the uleb128 encoded length
+    while *dex.get(offset)? & 0x80 != 0 {
+      offset += 1;
+    }
+    offset += 1;
+
+    let length = dex.get(offset..)?.iter().position(|byte| *byte == 0)?;
+    let descriptor = String::from_utf8_lossy(&dex[offset..offset + length]);
+
+    // only classes, e.g. `Lcom/example/Class;`
+    if let Some(class) = descriptor
+      .strip_prefix('L')
+      .and_then(|class| class.strip_suffix(';'))
+    {
+      class_names.push(class.replace('/', "."));
+    }
+  }
+
+  Some(class_names)
+}
+
+impl Repository {
+  /// Scans an apk for trackers and non-free libraries
+  ///
+  /// Uses the database configured in
+  /// [`UploadPolicy::signature_database`](super::UploadPolicy::signature_database) or
+  /// [`SignatureDatabase::bundled`] if none is configured.
+  pub fn scan_apk(&self, apk_path: &PathBuf) -> Result<ScanReport> {
+    let database = match &self.upload_policy().signature_database {
+      Some(database_path) => SignatureDatabase::from_file(database_path)?,
+      None => SignatureDatabase::bundled(),
+    };
+
+    database.scan(apk_path)
+  }
+}
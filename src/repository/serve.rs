@@ -0,0 +1,221 @@
+//! Extension of Repository used to preview the repository locally
+//!
+//! Serves `repo/` and `archive/` the same way a web server would, so an emulator or the
+//! F-Droid client can use `http://<addr>/repo` directly.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::net::ToSocketAddrs;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use log::{debug, info, warn};
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+
+use crate::error::{Error, Result};
+
+use super::s3::content_type;
+use super::Repository;
+
+impl Repository {
+  /// Serves `repo/` and `archive/` over http until the process is stopped
+  ///
+  /// Supports `HEAD` and `GET` requests, single `Range` requests and conditional requests
+  /// with `If-None-Match`.
+  ///
+  /// # Error
+  /// Returns an error if the address can't be bound
+  pub fn serve(&self, addr: impl ToSocketAddrs) -> Result<()> {
+    let server = Server::http(addr).map_err(|err| Error::Http(err.to_string()))?;
+
+    info!("Serving repository at http://{}/repo", server.server_addr());
+
+    for request in server.incoming_requests() {
+      debug!("{} {}", request.method(), request.url());
+
+      let response = match self.served_file(request.url()) {
+        Some(file_path) => file_response(&request, &file_path),
+        None => Ok(empty_response(404)),
+      };
+
+      let result = match response {
+        Ok(response) => request.respond(response),
+        Err(err) => {
+          warn!("Could not serve {}: {err}", request.url());
+          request.respond(empty_response(500))
+        }
+      };
+
+      if let Err(err) = result {
+        debug!("Could not send response: {err}");
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Maps the path of a url to a file inside of `repo/` or `archive/`
+  ///
+  /// Returns [None] if the file does not exist or the path leaves the directory.
+  fn served_file(&self, url: &str) -> Option<PathBuf> {
+    let path = percent_decode(url.split(['?', '#']).next()?)?;
+    let path = Path::new(&path);
+
+    let mut components = path.components().skip_while(|c| c == &Component::RootDir);
+    let root = match components.next()?.as_os_str().to_str()? {
+      "repo" => self.repo_path(),
+      "archive" => self.archive_path(),
+      _ => return None,
+    };
+
+    let mut file_path = root;
+    for component in components {
+      match component {
+        Component::Normal(name) => file_path.push(name),
+        _ => return None,
+      }
+    }
+
+    if file_path.is_dir() {
+      file_path.push("index.html");
+    }
+
+    file_path.is_file().then_some(file_path)
+  }
+}
+
+/// Creates the response for an existing file
+fn file_response(request: &Request, file_path: &PathBuf) -> Result<Response<Box<dyn Read + Send>>> {
+  if !matches!(request.method(), Method::Get | Method::Head) {
+    return Ok(empty_response(405));
+  }
+
+  let metadata = file_path.metadata()?;
+  let size = metadata.len();
+
+  let modified = metadata
+    .modified()?
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_nanos())
+    .unwrap_or_default();
+  let etag = format!("\"{size:x}-{modified:x}\"");
+
+  let header_value = |name: &'static str| {
+    request
+      .headers()
+      .iter()
+      .find(|header| header.field.equiv(name))
+      .map(|header| header.value.as_str().to_owned())
+  };
+
+  let mut headers = vec![
+    header("ETag", &etag),
+    header("Accept-Ranges", "bytes"),
+    header("Content-Type", content_type(file_path)),
+  ];
+
+  if header_value("If-None-Match").is_some_and(|value| {
+    value
+      .split(',')
+      .any(|tag| tag.trim() == etag || tag.trim() == "*")
+  }) {
+    return Ok(Response::new(
+      StatusCode(304),
+      headers,
+      Box::new(std::io::empty()),
+      Some(0),
+      None,
+    ));
+  }
+
+  let mut file = File::open(file_path)?;
+
+  let (status, start, length) = match header_value("Range") {
+    Some(range) => match parse_range(&range, size) {
+      Some((start, end)) => {
+        headers.push(header(
+          "Content-Range",
+          &format!("bytes {start}-{end}/{size}"),
+        ));
+        (206, start, end - start + 1)
+      }
+      None => {
+        headers.push(header("Content-Range", &format!("bytes */{size}")));
+        return Ok(Response::new(
+          StatusCode(416),
+          headers,
+          Box::new(std::io::empty()),
+          Some(0),
+          None,
+        ));
+      }
+    },
+    None => (200, 0, size),
+  };
+
+  file.seek(SeekFrom::Start(start))?;
+
+  Ok(Response::new(
+    StatusCode(status),
+    headers,
+    Box::new(file.take(length)),
+    Some(length as usize),
+    None,
+  ))
+}
+
+/// Parses a `Range` header with a single range and returns the first and last byte
+///
+/// Returns [None] if the range is invalid or not satisfiable
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+  let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+
+  let (start, end) = match (start.trim(), end.trim()) {
+    // suffix range, e.g. the last 500 bytes
+    ("", suffix) => (
+      size.checked_sub(suffix.parse::<u64>().ok()?.min(size))?,
+      size.checked_sub(1)?,
+    ),
+    (start, "") => (start.parse().ok()?, size.checked_sub(1)?),
+    (start, end) => (
+      start.parse().ok()?,
+      end.parse::<u64>().ok()?.min(size.checked_sub(1)?),
+    ),
+  };
+
+  (start <= end && end < size).then_some((start, end))
+}
+
+fn empty_response(status: u16) -> Response<Box<dyn Read + Send>> {
+  Response::new(
+    StatusCode(status),
+    vec![],
+    Box::new(std::io::empty()),
+    Some(0),
+    None,
+  )
+}
+
+fn header(name: &str, value: &str) -> Header {
+  Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("invalid header")
+}
+
+/// Decodes `%XX` sequences of an url path
+fn percent_decode(path: &str) -> Option<String> {
+  let bytes = path.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut index = 0;
+
+  while index < bytes.len() {
+    if bytes[index] == b'%' {
+      let hex = std::str::from_utf8(bytes.get(index + 1..index + 3)?).ok()?;
+      decoded.push(u8::from_str_radix(hex, 16).ok()?);
+      index += 3;
+    } else {
+      decoded.push(bytes[index]);
+      index += 1;
+    }
+  }
+
+  String::from_utf8(decoded).ok()
+}
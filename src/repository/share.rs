@@ -0,0 +1,155 @@
+//! Extension of Repository used to share the repository with users
+//!
+//! See [Setup an F-Droid App Repo](https://f-droid.org/en/docs/Setup_an_F-Droid_App_Repo/)
+
+use std::process::Command;
+
+use log::debug;
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+
+use super::Repository;
+
+#[cfg(feature = "qr")]
+use std::path::PathBuf;
+
+#[cfg(feature = "qr")]
+use log::info;
+
+/// Image format of a rendered QR code
+///
+/// See [Repository::write_qr_code]
+#[cfg(feature = "qr")]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum QrFormat {
+  /// Scalable vector graphic, as used by fdroidserver in `repo/index.html`
+  Svg,
+  /// Portable network graphic
+  Png,
+}
+
+/// Environment variable passing the keystore password to `keytool`
+const KEYSTORE_PASSWORD_ENV: &str = "FDROID_KEYSTORE_PASSWORD";
+
+impl Repository {
+  /// Returns the SHA-256 fingerprint of the repository signing certificate
+  ///
+  /// The fingerprint is returned as an uppercase hex string without separators,
+  /// which is the format expected by the F-Droid client.
+  ///
+  /// Runs `keytool -exportcert` on the keystore in [`Repository::keystore_path`].
+  ///
+  /// # Error
+  /// Returns an error if the config file can't be read or the certificate can't be exported
+  pub fn fingerprint(&self) -> Result<String> {
+    let config_file = self.get_config()?;
+
+    let command = format!(
+      "keytool -exportcert -keystore {:?} -alias {}",
+      self.keystore_path(),
+      config_file.repo_keyalias
+    );
+
+    let output = Command::new("keytool")
+      .arg("-exportcert")
+      .arg("-keystore")
+      .arg(self.keystore_path())
+      // an environment variable is not visible to other users like the command line
+      .arg("-storepass:env")
+      .arg(KEYSTORE_PASSWORD_ENV)
+      .env(KEYSTORE_PASSWORD_ENV, self.keystore_password()?)
+      .arg("-alias")
+      .arg(&config_file.repo_keyalias)
+      .output()
+      .map_err(|err| {
+        debug!("Error spawning keytool: {err:#?}");
+        Error::Run(command.clone())
+      })?;
+
+    if !output.status.success() || output.stdout.is_empty() {
+      debug!(
+        "keytool failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+      );
+      return Err(Error::Run(command));
+    }
+
+    Ok(hex::encode_upper(Sha256::digest(&output.stdout)))
+  }
+
+  /// Returns the link users can open to add this repository to their F-Droid client
+  ///
+  /// Combines [`Config::repo_url`](super::Config::repo_url) with [`Repository::fingerprint`],
+  /// e.g. `fdroidrepos://example.org/fdroid/repo?fingerprint=...`
+  ///
+  /// # Error
+  /// Returns an error if `repo_url` is not set or the fingerprint can't be computed
+  pub fn share_url(&self) -> Result<String> {
+    let repo_url = self
+      .config()?
+      .repo_url
+      .ok_or(Error::MissingConfig("repo_url".to_owned()))?;
+
+    Ok(share_url(&repo_url, &self.fingerprint()?))
+  }
+
+  /// Renders [`Repository::share_url`] as a QR code and writes it to `file_path`
+  ///
+  /// # Error
+  /// Returns an error if the share url can't be created or the image can't be written
+  #[cfg(feature = "qr")]
+  pub fn write_qr_code(&self, file_path: &PathBuf, format: QrFormat) -> Result<()> {
+    use crate::error::InvalidFile;
+    use qrcode::render::svg;
+    use qrcode::QrCode;
+
+    info!("Writing QR code to {file_path:?}");
+
+    let code = QrCode::new(self.share_url()?).map_err(|err| {
+      Error::InvalidFile(InvalidFile::with_reason(
+        file_path.clone(),
+        &format!("Could not encode QR code: {err}"),
+      ))
+    })?;
+
+    match format {
+      QrFormat::Svg => {
+        let image = code.render::<svg::Color>().min_dimensions(200, 200).build();
+        std::fs::write(file_path, image)?;
+      }
+      QrFormat::Png => {
+        let image = code
+          .render::<image::Luma<u8>>()
+          .min_dimensions(200, 200)
+          .build();
+        image
+          .save_with_format(file_path, image::ImageFormat::Png)
+          .map_err(|err| {
+            Error::InvalidFile(InvalidFile::with_reason(
+              file_path.clone(),
+              &err.to_string(),
+            ))
+          })?;
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// Builds the share link from a repository url and a fingerprint
+///
+/// `https` urls are mapped to `fdroidrepos`, everything else to `fdroidrepo`.
+pub(crate) fn share_url(repo_url: &str, fingerprint: &str) -> String {
+  let (scheme, rest) = match repo_url.split_once("://") {
+    Some(("https", rest)) => ("fdroidrepos", rest),
+    Some((_, rest)) => ("fdroidrepo", rest),
+    None => ("fdroidrepos", repo_url),
+  };
+
+  format!(
+    "{scheme}://{}?fingerprint={fingerprint}",
+    rest.trim_end_matches('/')
+  )
+}
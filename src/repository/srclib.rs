@@ -0,0 +1,186 @@
+//! For working with [srclibs](https://f-droid.org/en/docs/Build_Metadata_Reference/#build_srclibs),
+//! libraries whose source code is fetched separately and used by [Builds::srclibs]
+//!
+//! Each srclib is stored in `srclibs/<Name>.yml` and referenced by builds as `Name@ref`
+//! (optionally prefixed with `number:` for Ant projects).
+
+use std::fs;
+use std::path::PathBuf;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, InvalidFile, Result};
+use crate::metadata::{Builds, RepoType};
+
+use super::Repository;
+
+/// [DTO](https://en.wikipedia.org/wiki/Data_transfer_object) containing the metadata of a single srclib
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+#[allow(non_snake_case)]
+pub struct Srclib {
+  /// The type of the repository, in the same way as [AppMetadata::RepoType](crate::metadata::AppMetadata::RepoType)
+  pub RepoType: Option<RepoType>,
+  /// The repository location, in the same way as [AppMetadata::Repo](crate::metadata::AppMetadata::Repo)
+  pub Repo: Option<String>,
+  /// The directory of the library inside of the repository.
+  ///
+  /// Can be a comma separated list, for when directories are renamed by upstream. See [Srclib::subdirs]
+  pub Subdir: Option<String>,
+  /// A shell command which is run in the library directory after checking it out, e.g. to update the project with a particular target
+  pub Prepare: Option<String>,
+}
+
+impl Srclib {
+  /// Returns all directories of [Srclib::Subdir]
+  pub fn subdirs(&self) -> Vec<&str> {
+    self
+      .Subdir
+      .iter()
+      .flat_map(|subdir| subdir.split(','))
+      .map(str::trim)
+      .filter(|subdir| !subdir.is_empty())
+      .collect()
+  }
+}
+
+/// A parsed entry of [Builds::srclibs], e.g. `1:FooBar@v1.0`
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct SrclibReference {
+  /// name of the srclib file without extension
+  pub name: String,
+  /// tag, branch or commit to check out
+  pub git_ref: String,
+  /// number of the library reference in `project.properties` (only for Ant projects)
+  pub number: Option<u32>,
+}
+
+impl SrclibReference {
+  /// Parses an entry of [Builds::srclibs]
+  ///
+  /// Returns [None] if the name or the ref is missing
+  pub fn parse(reference: &str) -> Option<Self> {
+    let (name, git_ref) = reference.trim().split_once('@')?;
+
+    let (number, name) = match name.split_once(':') {
+      Some((number, name)) => (Some(number.parse().ok()?), name),
+      None => (None, name),
+    };
+
+    if name.is_empty() || git_ref.is_empty() {
+      return None;
+    }
+
+    Some(Self {
+      name: name.to_owned(),
+      git_ref: git_ref.to_owned(),
+      number,
+    })
+  }
+}
+
+impl Builds {
+  /// Parses all entries of [Builds::srclibs], invalid entries are returned as [Err]
+  pub fn srclib_references(&self) -> Vec<std::result::Result<SrclibReference, String>> {
+    self
+      .srclibs
+      .iter()
+      .flatten()
+      .map(|reference| SrclibReference::parse(reference).ok_or(reference.clone()))
+      .collect()
+  }
+}
+
+impl Repository {
+  /// gets the file path of a srclib
+  ///
+  /// does not check if the srclib exists
+  ///
+  /// # Error
+  /// Returns [Error::InvalidFile] if the name is empty or contains a path separator or `..`,
+  /// so the file would not be inside of [Repository::srclibs_path]
+  pub fn srclib_path(&self, name: &str) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+      return Err(Error::InvalidFile(InvalidFile::with_reason(
+        self.srclibs_path().join(name),
+        "Invalid srclib name",
+      )));
+    }
+
+    Ok(self.srclibs_path().join(format!("{name}.yml")))
+  }
+
+  /// Returns the names of all srclibs (sorted)
+  ///
+  /// # Error
+  /// Returns an error if the srclibs directory exists but can't be read
+  pub fn srclibs(&self) -> Result<Vec<String>> {
+    let srclibs_path = self.srclibs_path();
+    if !srclibs_path.is_dir() {
+      return Ok(vec![]);
+    }
+
+    let mut names: Vec<String> = fs::read_dir(srclibs_path)?
+      .filter_map(|entry| entry.ok())
+      .map(|entry| entry.path())
+      .filter(|path| path.is_file())
+      .filter(|path| path.extension().is_some_and(|extension| extension == "yml"))
+      .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+      .collect();
+    names.sort();
+
+    Ok(names)
+  }
+
+  /// Reads the [Srclib] with the given name
+  ///
+  /// # Error
+  /// - throws an error if the name is invalid (see [Repository::srclib_path])
+  /// - throws an error if the srclib does not exist
+  /// - throws an error if the file can't be mapped
+  pub fn srclib(&self, name: &str) -> Result<Srclib> {
+    let srclib_path = self.srclib_path(name)?;
+
+    if !srclib_path.is_file() {
+      return Err(Error::NotAFile(srclib_path));
+    }
+
+    serde_yaml::from_str(&fs::read_to_string(srclib_path)?).map_err(Error::from)
+  }
+
+  /// Creates or replaces a srclib
+  ///
+  /// # Error
+  /// Returns an error if the name is invalid (see [Repository::srclib_path]) or the srclib
+  /// can't be serialized or written
+  pub fn set_srclib(&self, name: &str, srclib: &Srclib) -> Result<()> {
+    info!("Setting srclib {name}");
+    let srclib_path = self.srclib_path(name)?;
+
+    fs::create_dir_all(self.srclibs_path())?;
+    fs::write(srclib_path, serde_yaml::to_string(srclib)?)?;
+
+    Ok(())
+  }
+
+  /// Deletes a srclib
+  ///
+  /// Builds which still reference it are reported by [Repository::lint].
+  ///
+  /// # Error
+  /// Returns an error if the name is invalid (see [Repository::srclib_path]) or the srclib
+  /// does not exist
+  pub fn delete_srclib(&self, name: &str) -> Result<()> {
+    info!("Deleting srclib {name}");
+    let srclib_path = self.srclib_path(name)?;
+
+    if !srclib_path.is_file() {
+      warn!("Srclib {name} does not exist!");
+      return Err(Error::NotAFile(srclib_path));
+    }
+
+    fs::remove_file(srclib_path)?;
+
+    Ok(())
+  }
+}
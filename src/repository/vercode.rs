@@ -0,0 +1,345 @@
+//! Parser and evaluator for [VercodeOperation](crate::metadata::AppMetadata::VercodeOperation)
+//!
+//! fdroidserver evaluates the operations with a restricted python `eval`. The same subset is
+//! supported here without evaluating arbitrary code: integers, `%c` (the upstream version
+//! code), `+`, `-`, `*`, `**` and parentheses.
+//!
+//! ```
+//! # use fdroid::VercodeOperation;
+//! let operation: VercodeOperation = "10 * %c + 1".parse().unwrap();
+//! assert_eq!(operation.apply(123), Some(1231));
+//! ```
+
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use log::warn;
+
+use crate::metadata::AppMetadata;
+
+/// A parsed operation, which can be applied to many version codes
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VercodeOperation {
+  source: String,
+  expression: Expression,
+}
+
+/// Reason why a [VercodeOperation] can't be parsed
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct VercodeSyntaxError {
+  /// position (in characters) inside of the operation
+  pub position: usize,
+  pub message: String,
+}
+
+impl fmt::Display for VercodeSyntaxError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{} at position {}", self.message, self.position)
+  }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum Expression {
+  Number(i128),
+  VersionCode,
+  Negate(Box<Expression>),
+  Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Operator {
+  Add,
+  Subtract,
+  Multiply,
+  Power,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Token {
+  Number(i128),
+  VersionCode,
+  Plus,
+  Minus,
+  Star,
+  DoubleStar,
+  Open,
+  Close,
+  End,
+}
+
+impl VercodeOperation {
+  /// Applies the operation to an upstream version code
+  ///
+  /// Returns [None] if the result overflows or is negative.
+  pub fn apply(&self, version_code: u64) -> Option<u64> {
+    u64::try_from(self.expression.evaluate(version_code.into())?).ok()
+  }
+
+  /// The operation as it has been parsed
+  pub fn as_str(&self) -> &str {
+    &self.source
+  }
+}
+
+impl FromStr for VercodeOperation {
+  type Err = VercodeSyntaxError;
+
+  fn from_str(operation: &str) -> Result<Self, Self::Err> {
+    let mut parser = Parser {
+      tokens: tokenize(operation)?,
+      index: 0,
+    };
+
+    let expression = parser.expression()?;
+    let (position, token) = parser.peek();
+    if token != Token::End {
+      return Err(VercodeSyntaxError {
+        position,
+        message: "unexpected token".to_owned(),
+      });
+    }
+
+    Ok(Self {
+      source: operation.to_owned(),
+      expression,
+    })
+  }
+}
+
+impl fmt::Display for VercodeOperation {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.source)
+  }
+}
+
+impl Expression {
+  fn evaluate(&self, version_code: i128) -> Option<i128> {
+    match self {
+      Expression::Number(number) => Some(*number),
+      Expression::VersionCode => Some(version_code),
+      Expression::Negate(expression) => expression.evaluate(version_code)?.checked_neg(),
+      Expression::Binary(operator, left, right) => {
+        let left = left.evaluate(version_code)?;
+        let right = right.evaluate(version_code)?;
+
+        match operator {
+          Operator::Add => left.checked_add(right),
+          Operator::Subtract => left.checked_sub(right),
+          Operator::Multiply => left.checked_mul(right),
+          Operator::Power => left.checked_pow(u32::try_from(right).ok()?),
+        }
+      }
+    }
+  }
+}
+
+fn tokenize(operation: &str) -> Result<Vec<(usize, Token)>, VercodeSyntaxError> {
+  let chars: Vec<char> = operation.chars().collect();
+  let mut tokens = vec![];
+  let mut index = 0;
+
+  while index < chars.len() {
+    let position = index;
+    let token = match chars[index] {
+      char if char.is_whitespace() => {
+        index += 1;
+        continue;
+      }
+      '0'..='9' => {
+        while chars.get(index).is_some_and(char::is_ascii_digit) {
+          index += 1;
+        }
+        let digits: String = chars[position..index].iter().collect();
+        let number = digits.parse().map_err(|_| VercodeSyntaxError {
+          position,
+          message: format!("number {digits} is too large"),
+        })?;
+        tokens.push((position, Token::Number(number)));
+        continue;
+      }
+      '%' if chars.get(index + 1) == Some(&'c') => {
+        index += 1;
+        Token::VersionCode
+      }
+      '+' => Token::Plus,
+      '-' => Token::Minus,
+      '*' if chars.get(index + 1) == Some(&'*') => {
+        index += 1;
+        Token::DoubleStar
+      }
+      '*' => Token::Star,
+      '(' => Token::Open,
+      ')' => Token::Close,
+      char => {
+        return Err(VercodeSyntaxError {
+          position,
+          message: format!("unsupported character '{char}'"),
+        })
+      }
+    };
+
+    tokens.push((position, token));
+    index += 1;
+  }
+
+  tokens.push((chars.len(), Token::End));
+  Ok(tokens)
+}
+
+/// Recursive descent parser with python's precedence:
+/// ```text
+/// expression = term (("+" | "-") term)*
+/// term       = unary ("*" unary)*
+/// unary      = ("+" | "-") unary | power
+/// power      = atom ("**" unary)?
+/// atom       = number | "%c" | "(" expression ")"
+/// ```
+struct Parser {
+  tokens: Vec<(usize, Token)>,
+  index: usize,
+}
+
+impl Parser {
+  fn peek(&self) -> (usize, Token) {
+    self.tokens[self.index]
+  }
+
+  fn next(&mut self) -> (usize, Token) {
+    let token = self.peek();
+    if token.1 != Token::End {
+      self.index += 1;
+    }
+    token
+  }
+
+  fn expression(&mut self) -> Result<Expression, VercodeSyntaxError> {
+    let mut expression = self.term()?;
+
+    loop {
+      let operator = match self.peek().1 {
+        Token::Plus => Operator::Add,
+        Token::Minus => Operator::Subtract,
+        _ => return Ok(expression),
+      };
+      self.next();
+      expression = Expression::Binary(operator, Box::new(expression), Box::new(self.term()?));
+    }
+  }
+
+  fn term(&mut self) -> Result<Expression, VercodeSyntaxError> {
+    let mut expression = self.unary()?;
+
+    while self.peek().1 == Token::Star {
+      self.next();
+      expression = Expression::Binary(
+        Operator::Multiply,
+        Box::new(expression),
+        Box::new(self.unary()?),
+      );
+    }
+
+    Ok(expression)
+  }
+
+  fn unary(&mut self) -> Result<Expression, VercodeSyntaxError> {
+    match self.peek().1 {
+      Token::Minus => {
+        self.next();
+        Ok(Expression::Negate(Box::new(self.unary()?)))
+      }
+      Token::Plus => {
+        self.next();
+        self.unary()
+      }
+      _ => self.power(),
+    }
+  }
+
+  fn power(&mut self) -> Result<Expression, VercodeSyntaxError> {
+    let base = self.atom()?;
+
+    if self.peek().1 == Token::DoubleStar {
+      self.next();
+      return Ok(Expression::Binary(
+        Operator::Power,
+        Box::new(base),
+        Box::new(self.unary()?),
+      ));
+    }
+
+    Ok(base)
+  }
+
+  fn atom(&mut self) -> Result<Expression, VercodeSyntaxError> {
+    match self.next() {
+      (_, Token::Number(number)) => Ok(Expression::Number(number)),
+      (_, Token::VersionCode) => Ok(Expression::VersionCode),
+      (position, Token::Open) => {
+        let expression = self.expression()?;
+        match self.next() {
+          (_, Token::Close) => Ok(expression),
+          (close_position, _) => Err(VercodeSyntaxError {
+            position: close_position,
+            message: format!("missing ')' for '(' at position {position}"),
+          }),
+        }
+      }
+      (position, Token::End) => Err(VercodeSyntaxError {
+        position,
+        message: "unexpected end".to_owned(),
+      }),
+      (position, _) => Err(VercodeSyntaxError {
+        position,
+        message: "expected a number, %c or '('".to_owned(),
+      }),
+    }
+  }
+}
+
+impl AppMetadata {
+  /// Parses all operations of `VercodeOperation`
+  ///
+  /// # Error
+  /// Returns the first syntax error
+  pub fn vercode_operations(&self) -> Result<Vec<VercodeOperation>, VercodeSyntaxError> {
+    self
+      .VercodeOperation
+      .iter()
+      .flatten()
+      .map(|operation| operation.parse())
+      .collect()
+  }
+
+  /// Returns all version codes which are built for an upstream version code
+  ///
+  /// This is one version code per operation of `VercodeOperation` (e.g. one per ABI) or only
+  /// the upstream version code if it is not set. Useful to check that all variants of a
+  /// version have been uploaded.
+  ///
+  /// # Error
+  /// Returns the first syntax error of `VercodeOperation`
+  pub fn expected_version_codes(
+    &self,
+    upstream_version_code: u64,
+  ) -> Result<BTreeSet<u64>, VercodeSyntaxError> {
+    let operations = self.vercode_operations()?;
+
+    if operations.is_empty() {
+      return Ok(BTreeSet::from([upstream_version_code]));
+    }
+
+    Ok(
+      operations
+        .iter()
+        .filter_map(|operation| {
+          let version_code = operation.apply(upstream_version_code);
+          if version_code.is_none() {
+            warn!("VercodeOperation \"{operation}\" does not result in a valid version code for {upstream_version_code}");
+          }
+          version_code
+        })
+        .collect(),
+    )
+  }
+}
@@ -0,0 +1,250 @@
+//! Verification of [reproducible builds](https://f-droid.org/en/docs/Reproducible_Builds/)
+//!
+//! Two apks are equivalent if all their zip entries are the same, except for the signature files
+//! inside of `META-INF/`. The v2/v3 signing blocks are not part of any zip entry and are
+//! therefore ignored automatically.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::{debug, info, warn};
+use regex::Regex;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+
+use crate::aapt::*;
+use crate::error::{Error, InvalidFile, Result};
+
+use super::Repository;
+
+/// Reason why a zip entry differs between two apks
+#[derive(Debug, Clone, Copy, Serialize, Eq, PartialEq)]
+pub enum MismatchKind {
+  /// The entry only exists in the reference apk
+  MissingInCandidate,
+  /// The entry only exists in the candidate apk
+  MissingInReference,
+  /// The uncompressed content of the entry differs
+  ContentDiffers,
+  /// The content is the same, but it has been compressed differently
+  CompressionDiffers,
+}
+
+/// A single zip entry that differs between two apks
+#[derive(Debug, Clone, Serialize, Eq, PartialEq)]
+pub struct EntryMismatch {
+  /// path of the entry inside of the apk
+  pub name: String,
+  pub kind: MismatchKind,
+}
+
+/// Result of [verify_reproducible]
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct ReproducibleReport {
+  /// all entries that differ, sorted by name
+  pub mismatches: Vec<EntryMismatch>,
+}
+
+impl ReproducibleReport {
+  /// Returns true if both apks are equivalent
+  pub fn is_reproducible(&self) -> bool {
+    self.mismatches.is_empty()
+  }
+}
+
+/// Summary of a single zip entry used for comparison
+struct EntrySummary {
+  hash: Vec<u8>,
+  compression: String,
+}
+
+/// Compares two apks while ignoring their signatures
+///
+/// Works the same as `fdroid verify`: all zip entries have to be the same, except for the
+/// `MANIFEST.MF` and the signature files (`*.SF`, `*.RSA`, `*.DSA`, `*.EC`) inside of `META-INF/`.
+///
+/// # Error
+/// Returns an error if one of the files can't be read as a zip archive
+pub fn verify_reproducible(
+  reference_apk: &PathBuf,
+  candidate_apk: &PathBuf,
+) -> Result<ReproducibleReport> {
+  info!("Comparing {reference_apk:?} with {candidate_apk:?}");
+
+  let reference = read_entries(reference_apk)?;
+  let candidate = read_entries(candidate_apk)?;
+
+  let mut mismatches = vec![];
+
+  for (name, reference_entry) in &reference {
+    let kind = match candidate.get(name) {
+      None => Some(MismatchKind::MissingInCandidate),
+      Some(candidate_entry) if candidate_entry.hash != reference_entry.hash => {
+        Some(MismatchKind::ContentDiffers)
+      }
+      Some(candidate_entry) if candidate_entry.compression != reference_entry.compression => {
+        Some(MismatchKind::CompressionDiffers)
+      }
+      Some(_) => None,
+    };
+
+    if let Some(kind) = kind {
+      mismatches.push(EntryMismatch {
+        name: name.clone(),
+        kind,
+      });
+    }
+  }
+
+  for name in candidate.keys() {
+    if !reference.contains_key(name) {
+      mismatches.push(EntryMismatch {
+        name: name.clone(),
+        kind: MismatchKind::MissingInReference,
+      });
+    }
+  }
+
+  mismatches.sort_by(|a, b| a.name.cmp(&b.name));
+
+  for mismatch in &mismatches {
+    debug!("Mismatch: {mismatch:?}");
+  }
+
+  Ok(ReproducibleReport { mismatches })
+}
+
+/// Returns true if the zip entry belongs to the v1 signature
+fn is_signature_entry(name: &str) -> bool {
+  let regex = Regex::new(r"^META-INF/(?:[^/]+\.(?:SF|RSA|DSA|EC)|MANIFEST\.MF)$").unwrap();
+
+  regex.is_match(name)
+}
+
+/// Reads all (non signature) entries of an apk
+fn read_entries(apk_path: &PathBuf) -> Result<BTreeMap<String, EntrySummary>> {
+  let invalid =
+    |reason: String| Error::InvalidFile(InvalidFile::with_reason(apk_path.clone(), &reason));
+
+  if !apk_path.is_file() {
+    return Err(Error::NotAFile(apk_path.clone()));
+  }
+
+  let mut archive =
+    ZipArchive::new(File::open(apk_path)?).map_err(|err| invalid(err.to_string()))?;
+
+  let mut entries = BTreeMap::new();
+
+  for index in 0..archive.len() {
+    let mut entry = archive
+      .by_index(index)
+      .map_err(|err| invalid(err.to_string()))?;
+
+    if entry.is_dir() || is_signature_entry(entry.name()) {
+      continue;
+    }
+
+    let mut content = vec![];
+    entry.read_to_end(&mut content)?;
+
+    entries.insert(
+      entry.name().to_owned(),
+      EntrySummary {
+        hash: Sha256::digest(&content).to_vec(),
+        compression: entry.compression().to_string(),
+      },
+    );
+  }
+
+  Ok(entries)
+}
+
+impl Repository {
+  /// Publishes an unsigned apk with the signature of an upstream apk instead of the repository key
+  ///
+  /// Both apks are compared with [verify_reproducible] first. If they are not equivalent,
+  /// nothing is copied.
+  ///
+  /// Like fdroidserver, the signature is extracted into [`Repository::signatures_path`] with
+  /// [apksigcopier](https://github.com/obfusk/apksigcopier) `extract` and the unsigned apk is
+  /// placed in [`Repository::unsigned_path`]. `fdroid publish` then applies the upstream
+  /// signature to it.
+  ///
+  /// Returns the path to the unsigned apk inside of [`Repository::unsigned_path`].
+  ///
+  /// # Error
+  /// Returns an error if the apks are not equivalent or `apksigcopier` fails
+  pub fn copy_signature(&self, signed_apk: &PathBuf, unsigned_apk: &PathBuf) -> Result<PathBuf> {
+    info!("Copying signature of {signed_apk:?} onto {unsigned_apk:?}");
+
+    let report = verify_reproducible(signed_apk, unsigned_apk)?;
+
+    if !report.is_reproducible() {
+      warn!("{unsigned_apk:?} is not equivalent to {signed_apk:?}");
+
+      return Err(Error::InvalidFile(InvalidFile::with_reason(
+        unsigned_apk.clone(),
+        &format!(
+          "Not reproducible, {} entries differ",
+          report.mismatches.len()
+        ),
+      )));
+    }
+
+    // name the file like fdroid does (package_versioncode.apk)
+    let apk_metadata = self.apk_info(unsigned_apk)?;
+    let version_code = get_version_code(&apk_metadata).ok_or(Error::InvalidFile(
+      InvalidFile::with_reason(unsigned_apk.clone(), "Version Code not found!"),
+    ))?;
+    let package_name = get_name(&apk_metadata).ok_or(Error::InvalidFile(
+      InvalidFile::with_reason(unsigned_apk.clone(), "Name not found!"),
+    ))?;
+
+    let signatures_path = self.signatures_path(&package_name, version_code.into());
+    // signature files of an earlier upstream apk must not be mixed with the new ones
+    if signatures_path.exists() {
+      fs::remove_dir_all(&signatures_path)?;
+    }
+    fs::create_dir_all(&signatures_path)?;
+    run_apksigcopier(signed_apk, &signatures_path)?;
+
+    let output_path = self
+      .unsigned_path()?
+      .join(format!("{package_name}_{version_code}.apk"));
+
+    // the unsigned apk may already be in place, e.g. after Repository::build
+    if unsigned_apk.canonicalize()? != output_path.canonicalize().unwrap_or_default() {
+      fs::copy(unsigned_apk, &output_path)?;
+    }
+
+    Ok(output_path)
+  }
+}
+
+/// Runs `apksigcopier extract <signed> <output_dir>`
+fn run_apksigcopier(signed_apk: &Path, output_dir: &Path) -> Result<()> {
+  let command = format!("apksigcopier extract {signed_apk:?} {output_dir:?}");
+
+  let status = Command::new("apksigcopier")
+    .arg("extract")
+    .arg(signed_apk)
+    .arg(output_dir)
+    .status()
+    .map_err(|err| {
+      debug!("Error spawning apksigcopier: {err:#?}");
+      Error::Run(command.clone())
+    })?;
+
+  if status.success() {
+    Ok(())
+  } else {
+    // do not leave partial signature files behind, publish would try to apply them
+    fs::remove_dir_all(output_dir)?;
+
+    Err(Error::Run(command))
+  }
+}
@@ -0,0 +1,335 @@
+//! Extension of Repository used to send [RepositoryEvent]s to http endpoints
+//!
+//! The endpoints are read from `webhooks` in the config file:
+//! ```yaml
+//! webhooks:
+//!   - url: https://example.org/hooks/fdroid
+//!     secret: shared-secret
+//!     # optional, all events except BuildLog are sent by default
+//!     events: [AppAdded, AppDeleted, IndexRebuilt]
+//! ```
+//!
+//! Every event is sent as a `POST` request with a json body and the headers
+//! - `X-Fdroid-Event`: name of the event, e.g. `IndexRebuilt`
+//! - `X-Fdroid-Delivery`: id of the message, which stays the same on retries
+//! - `X-Fdroid-Signature`: `sha256=<hex encoded HMAC-SHA256 of the body keyed with the secret>`
+//!
+//! Messages which can't be delivered are stored in [Repository::webhook_queue_path] and
+//! retried with exponential backoff by [WebhookDispatcher::retry].
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+
+use super::s3::hmac_sha256;
+use super::{Repository, RepositoryEvent, RepositoryObserver};
+
+/// A single entry of `webhooks` inside of the config file
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct WebhookEndpoint {
+  pub url: String,
+  /// key used to sign the body, so the receiver can verify the sender
+  pub secret: String,
+  /// names of the events which are sent, all events except `BuildLog` are sent if it is empty
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub events: Vec<String>,
+}
+
+impl WebhookEndpoint {
+  fn accepts(&self, event_name: &str) -> bool {
+    if self.events.is_empty() {
+      // a build would send a request for every line of its output
+      return event_name != "BuildLog";
+    }
+    self.events.iter().any(|event| event == event_name)
+  }
+}
+
+/// A message for a single endpoint, stored in the retry queue until it is delivered
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct WebhookMessage {
+  pub id: String,
+  pub url: String,
+  /// name of the event
+  pub event: String,
+  pub body: String,
+  /// value of the `X-Fdroid-Signature` header
+  pub signature: String,
+  /// unix time (in seconds) the message has been created
+  pub created: u64,
+  /// number of failed deliveries
+  pub attempts: u32,
+  /// unix time (in seconds) of the next delivery attempt
+  pub next_attempt: u64,
+  pub last_error: Option<String>,
+}
+
+/// Result of [WebhookDispatcher::retry]
+#[derive(Debug, Clone, Default, Serialize, Eq, PartialEq)]
+pub struct WebhookReport {
+  /// ids of the messages which have been delivered
+  pub delivered: Vec<String>,
+  /// ids of the messages which failed again and stay in the queue
+  pub failed: Vec<String>,
+  /// ids of the messages which reached the maximum number of attempts and have been removed
+  pub dropped: Vec<String>,
+}
+
+/// Sends [RepositoryEvent]s to all configured [WebhookEndpoint]s
+///
+/// Register it with [Repository::add_observer] to send every event of the repository.
+/// The first delivery happens synchronously, call [WebhookDispatcher::retry] regularly
+/// (e.g. every minute) to deliver queued messages.
+///
+/// ```no_run
+/// # use std::path::PathBuf;
+/// # use std::sync::Arc;
+/// # use fdroid::Repository;
+/// let mut repository = Repository::new(PathBuf::from("/fdroid")).unwrap();
+/// let dispatcher = repository.webhook_dispatcher().unwrap();
+/// repository.add_observer(Arc::new(dispatcher));
+/// ```
+#[derive(Debug, Clone)]
+pub struct WebhookDispatcher {
+  queue_path: PathBuf,
+  endpoints: Vec<WebhookEndpoint>,
+  /// delay after the first failed delivery, doubled after every further failure
+  initial_backoff: Duration,
+  max_backoff: Duration,
+  /// messages are dropped after this many failed deliveries
+  max_attempts: u32,
+  /// timeout of a single request
+  timeout: Duration,
+}
+
+impl WebhookDispatcher {
+  /// Creates a dispatcher storing undelivered messages in `queue_path`
+  ///
+  /// By default, messages are retried after 30 seconds, doubling the delay up to 6 hours,
+  /// and dropped after 10 failed deliveries.
+  pub fn new(queue_path: PathBuf, endpoints: Vec<WebhookEndpoint>) -> Self {
+    Self {
+      queue_path,
+      endpoints,
+      initial_backoff: Duration::from_secs(30),
+      max_backoff: Duration::from_secs(6 * 60 * 60),
+      max_attempts: 10,
+      timeout: Duration::from_secs(10),
+    }
+  }
+
+  /// Sets the delay after the first failed delivery and the maximum delay
+  pub fn with_backoff(mut self, initial_backoff: Duration, max_backoff: Duration) -> Self {
+    self.initial_backoff = initial_backoff;
+    self.max_backoff = max_backoff;
+    self
+  }
+
+  /// Sets the number of failed deliveries after which a message is dropped
+  pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+    self.max_attempts = max_attempts;
+    self
+  }
+
+  /// Sets the timeout of a single request
+  pub fn with_timeout(mut self, timeout: Duration) -> Self {
+    self.timeout = timeout;
+    self
+  }
+
+  pub fn endpoints(&self) -> &[WebhookEndpoint] {
+    &self.endpoints
+  }
+
+  /// Sends an event to all endpoints accepting it
+  ///
+  /// Messages which can't be delivered are added to the queue.
+  ///
+  /// # Error
+  /// Returns an error if the event can't be serialized or the queue can't be written
+  pub fn dispatch(&self, event: &RepositoryEvent) -> Result<()> {
+    let mut payload =
+      serde_json::to_value(event).map_err(|err| Error::JsonConvert(err.to_string()))?;
+    let event_name = payload["event"].as_str().unwrap_or_default().to_owned();
+    let created = unix_time(SystemTime::now());
+
+    for endpoint in &self.endpoints {
+      if !endpoint.accepts(&event_name) {
+        continue;
+      }
+
+      let id = Uuid::new_v4().to_string();
+      payload["id"] = serde_json::json!(id);
+      payload["timestamp"] = serde_json::json!(created);
+      let body = payload.to_string();
+
+      let mut message = WebhookMessage {
+        signature: format!(
+          "sha256={}",
+          hex::encode(hmac_sha256(endpoint.secret.as_bytes(), body.as_bytes()))
+        ),
+        id,
+        url: endpoint.url.clone(),
+        event: event_name.clone(),
+        body,
+        created,
+        attempts: 0,
+        next_attempt: created,
+        last_error: None,
+      };
+
+      if !self.deliver(&mut message) {
+        self.enqueue(&message)?;
+      }
+    }
+
+    Ok(())
+  }
+
+  /// Returns all messages of the queue, oldest first
+  ///
+  /// # Error
+  /// Returns an error if the queue can't be read
+  pub fn queued(&self) -> Result<Vec<WebhookMessage>> {
+    if !self.queue_path.is_dir() {
+      return Ok(vec![]);
+    }
+
+    let mut messages = vec![];
+
+    for entry in fs::read_dir(&self.queue_path)? {
+      let path = entry?.path();
+
+      if path.extension().is_none_or(|extension| extension != "json") {
+        continue;
+      }
+
+      match serde_json::from_str(&fs::read_to_string(&path)?) {
+        Ok(message) => messages.push(message),
+        Err(err) => warn!("Ignoring invalid webhook message {path:?}: {err}"),
+      }
+    }
+
+    messages.sort_by(|a: &WebhookMessage, b| (a.created, &a.id).cmp(&(b.created, &b.id)));
+
+    Ok(messages)
+  }
+
+  /// Delivers all queued messages whose next attempt is due
+  ///
+  /// **Note**: running it concurrently from multiple processes can deliver a message twice.
+  ///
+  /// # Error
+  /// Returns an error if the queue can't be read or written
+  pub fn retry(&self) -> Result<WebhookReport> {
+    let now = unix_time(SystemTime::now());
+    let mut report = WebhookReport::default();
+
+    for mut message in self.queued()? {
+      if message.next_attempt > now {
+        continue;
+      }
+
+      if self.deliver(&mut message) {
+        fs::remove_file(self.message_path(&message))?;
+        report.delivered.push(message.id);
+      } else if message.attempts >= self.max_attempts {
+        warn!(
+          "Dropping webhook message {} for {} after {} attempts",
+          message.id, message.url, message.attempts
+        );
+        fs::remove_file(self.message_path(&message))?;
+        report.dropped.push(message.id);
+      } else {
+        self.enqueue(&message)?;
+        report.failed.push(message.id);
+      }
+    }
+
+    Ok(report)
+  }
+
+  /// Sends a message once, returns true on success
+  ///
+  /// On failure, the attempt is recorded and the next attempt is scheduled.
+  fn deliver(&self, message: &mut WebhookMessage) -> bool {
+    debug!("Sending webhook {} to {}", message.id, message.url);
+
+    let result = ureq::post(&message.url)
+      .timeout(self.timeout)
+      .set("Content-Type", "application/json")
+      .set("X-Fdroid-Event", &message.event)
+      .set("X-Fdroid-Delivery", &message.id)
+      .set("X-Fdroid-Signature", &message.signature)
+      .send_string(&message.body);
+
+    match result {
+      Ok(_) => {
+        info!("Delivered webhook {} to {}", message.id, message.url);
+        true
+      }
+      Err(err) => {
+        warn!(
+          "Could not deliver webhook {} to {}: {err}",
+          message.id, message.url
+        );
+
+        let backoff = self
+          .initial_backoff
+          .saturating_mul(2_u32.saturating_pow(message.attempts))
+          .min(self.max_backoff);
+
+        message.attempts += 1;
+        message.next_attempt = unix_time(SystemTime::now() + backoff);
+        message.last_error = Some(err.to_string());
+        false
+      }
+    }
+  }
+
+  fn enqueue(&self, message: &WebhookMessage) -> Result<()> {
+    fs::create_dir_all(&self.queue_path)?;
+
+    let content =
+      serde_json::to_string_pretty(message).map_err(|err| Error::JsonConvert(err.to_string()))?;
+
+    fs::write(self.message_path(message), content).map_err(Error::from)
+  }
+
+  fn message_path(&self, message: &WebhookMessage) -> PathBuf {
+    self.queue_path.join(format!("{}.json", message.id))
+  }
+}
+
+impl RepositoryObserver for WebhookDispatcher {
+  fn notify(&self, event: &RepositoryEvent) {
+    if let Err(err) = self.dispatch(event) {
+      warn!("Could not dispatch webhooks: {err}");
+    }
+  }
+}
+
+impl Repository {
+  /// Creates a [WebhookDispatcher] for the endpoints configured in `webhooks`
+  ///
+  /// # Error
+  /// Returns an error if the config file can't be read
+  pub fn webhook_dispatcher(&self) -> Result<WebhookDispatcher> {
+    let endpoints = self.get_config()?.webhooks.unwrap_or_default();
+
+    Ok(WebhookDispatcher::new(self.webhook_queue_path(), endpoints))
+  }
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+  time
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_secs())
+    .unwrap_or_default()
+}
@@ -0,0 +1,369 @@
+//! REST api for managing a [Repository] over http
+//!
+//! All endpoints (except `GET /openapi.json`) require the header `Authorization: Bearer <token>`.
+//! Errors are returned as json in the form `{"error": "<kind>", "message": "<message>"}`,
+//! where `kind` is the name of the [Error] variant (see [Error::kind]).
+//!
+//! See `GET /openapi.json` for the full description of the api.
+
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use uuid::Uuid;
+
+use crate::error::{Error, InvalidFile, Result};
+use crate::metadata::AppMetadata;
+use crate::{Config, Repository};
+
+/// OpenAPI description of all endpoints
+const OPENAPI: &str = include_str!("openapi.json");
+
+/// Http server exposing the methods of a [Repository]
+///
+/// ```no_run
+/// # use std::path::PathBuf;
+/// # use fdroid::{ApiServer, Repository};
+/// let repository = Repository::new(PathBuf::from("/fdroid")).unwrap();
+/// ApiServer::new(repository, "secret-token").serve("0.0.0.0:8080").unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ApiServer {
+  repository: Repository,
+  /// token every request has to provide
+  token: String,
+}
+
+/// Response of a single endpoint
+struct ApiResponse {
+  status: u16,
+  content_type: &'static str,
+  body: String,
+}
+
+impl ApiResponse {
+  fn json(status: u16, value: &impl Serialize) -> Result<Self> {
+    Ok(Self {
+      status,
+      content_type: "application/json",
+      body: serde_json::to_string(value).map_err(|err| Error::JsonConvert(err.to_string()))?,
+    })
+  }
+
+  fn no_content() -> Result<Self> {
+    Ok(Self {
+      status: 204,
+      content_type: "application/json",
+      body: String::new(),
+    })
+  }
+
+  /// Error which is not caused by the repository (e.g. a missing token)
+  fn error(status: u16, kind: &str, message: &str) -> Self {
+    Self {
+      status,
+      content_type: "application/json",
+      body: json!({ "error": kind, "message": message }).to_string(),
+    }
+  }
+}
+
+impl From<Error> for ApiResponse {
+  fn from(error: Error) -> Self {
+    let status = match error {
+      Error::NotAFile(_) | Error::NotADirectory(_) | Error::PackageNotFound { .. } => 404,
+      Error::InvalidFile(_) | Error::JsonConvert(_) | Error::YAMLConvert(_) => 400,
+      Error::UploadPolicy(_) | Error::Integrity(_) => 422,
+      _ => 500,
+    };
+
+    Self::error(status, error.kind(), &error.to_string())
+  }
+}
+
+impl ApiServer {
+  /// Creates a new server, which only accepts requests containing `token`
+  pub fn new(repository: Repository, token: &str) -> Self {
+    Self {
+      repository,
+      token: token.to_owned(),
+    }
+  }
+
+  /// Handles requests until the process is stopped
+  ///
+  /// # Error
+  /// Returns an error if the address can't be bound
+  pub fn serve(&self, addr: impl ToSocketAddrs) -> Result<()> {
+    let server = Server::http(addr).map_err(|err| Error::Http(err.to_string()))?;
+
+    info!("Serving api at http://{}", server.server_addr());
+
+    for mut request in server.incoming_requests() {
+      debug!("{} {}", request.method(), request.url());
+
+      let response = self.handle(&mut request);
+
+      if response.status >= 500 {
+        warn!(
+          "{} {} failed: {}",
+          request.method(),
+          request.url(),
+          response.body
+        );
+      }
+
+      let result = request.respond(
+        Response::from_string(response.body)
+          .with_status_code(StatusCode(response.status))
+          .with_header(header("Content-Type", response.content_type)),
+      );
+
+      if let Err(err) = result {
+        debug!("Could not send response: {err}");
+      }
+    }
+
+    Ok(())
+  }
+
+  fn handle(&self, request: &mut Request) -> ApiResponse {
+    let url = request.url().to_owned();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let segments: Vec<&str> = path
+      .split('/')
+      .filter(|segment| !segment.is_empty())
+      .collect();
+
+    if request.method() == &Method::Get && segments == ["openapi.json"] {
+      return ApiResponse {
+        status: 200,
+        content_type: "application/json",
+        body: OPENAPI.to_owned(),
+      };
+    }
+
+    if !self.is_authorized(request) {
+      return ApiResponse::error(401, "Unauthorized", "Missing or invalid bearer token");
+    }
+
+    self
+      .route(request, &segments, query)
+      .unwrap_or_else(ApiResponse::from)
+  }
+
+  fn route(&self, request: &mut Request, segments: &[&str], query: &str) -> Result<ApiResponse> {
+    match (request.method(), segments) {
+      (Method::Get, ["apps"]) => ApiResponse::json(200, &self.repository.apps()?),
+      (Method::Post, ["apps"]) => self.upload_app(request, query),
+      (Method::Delete, ["apps", apk_name]) => {
+        validate_name(apk_name)?;
+        self.repository.delete_app(apk_name)?;
+        ApiResponse::no_content()
+      }
+      (Method::Get, ["metadata", package_name]) => {
+        validate_name(package_name)?;
+        ApiResponse::json(200, &self.repository.metadata(package_name)?)
+      }
+      (Method::Put, ["metadata", package_name]) => {
+        validate_name(package_name)?;
+        let metadata: AppMetadata = read_json(request)?;
+        self.repository.set_metadata(package_name, &metadata)?;
+        ApiResponse::no_content()
+      }
+      (Method::Get, ["config"]) => ApiResponse::json(200, &self.repository.config()?),
+      (Method::Put, ["config"]) => {
+        let config: Config = read_json(request)?;
+        self.repository.set_config(&config)?;
+        ApiResponse::no_content()
+      }
+      (Method::Post, ["image"]) => {
+        with_uploaded_file(request, |file_path| self.repository.set_image(file_path))?;
+        ApiResponse::no_content()
+      }
+      (_, ["apps"] | ["apps", _] | ["metadata", _] | ["config"] | ["image"]) => Ok(
+        ApiResponse::error(405, "MethodNotAllowed", "Method not allowed"),
+      ),
+      _ => Ok(ApiResponse::error(404, "NotFound", "Unknown endpoint")),
+    }
+  }
+
+  /// Uploads an apk, signs it if the query contains `sign=true`
+  fn upload_app(&self, request: &mut Request, query: &str) -> Result<ApiResponse> {
+    let sign = query.split('&').any(|pair| pair == "sign=true");
+
+    with_uploaded_file(request, |file_path| {
+      if sign {
+        self.repository.sign_app(file_path)
+      } else {
+        self.repository.add_app(file_path)
+      }
+    })?;
+
+    ApiResponse::no_content()
+  }
+
+  /// Checks the bearer token (in constant time)
+  fn is_authorized(&self, request: &Request) -> bool {
+    let Some(token) = request
+      .headers()
+      .iter()
+      .find(|header| header.field.equiv("Authorization"))
+      .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+    else {
+      return false;
+    };
+
+    token.len() == self.token.len()
+      && token
+        .bytes()
+        .zip(self.token.bytes())
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+  }
+}
+
+/// Rejects names which could be used to leave the repository directory
+fn validate_name(name: &str) -> Result<()> {
+  let valid = !name.starts_with('.')
+    && name
+      .chars()
+      .all(|char| char.is_ascii_alphanumeric() || matches!(char, '.' | '_' | '-'));
+
+  if valid {
+    Ok(())
+  } else {
+    Err(Error::InvalidFile(InvalidFile::with_reason(
+      PathBuf::from(name),
+      "Invalid name",
+    )))
+  }
+}
+
+fn read_body(request: &mut Request) -> Result<Vec<u8>> {
+  let mut body = vec![];
+  request.as_reader().read_to_end(&mut body)?;
+
+  Ok(body)
+}
+
+fn read_json<T: DeserializeOwned>(request: &mut Request) -> Result<T> {
+  serde_json::from_slice(&read_body(request)?).map_err(|err| Error::JsonConvert(err.to_string()))
+}
+
+/// Stores the multipart field `file` in a temporary directory (keeping its file name) and
+/// calls `action` with its path
+fn with_uploaded_file(
+  request: &mut Request,
+  action: impl FnOnce(&PathBuf) -> Result<()>,
+) -> Result<()> {
+  let invalid =
+    |reason: &str| Error::InvalidFile(InvalidFile::with_reason(PathBuf::from("file"), reason));
+
+  let content_type = request
+    .headers()
+    .iter()
+    .find(|header| header.field.equiv("Content-Type"))
+    .map(|header| header.value.as_str().to_owned())
+    .unwrap_or_default();
+
+  let boundary = content_type
+    .strip_prefix("multipart/form-data")
+    .and_then(|parameters| {
+      parameters
+        .split(';')
+        .find_map(|parameter| parameter.trim().strip_prefix("boundary="))
+    })
+    .map(|boundary| boundary.trim_matches('"').to_owned())
+    .ok_or(invalid("Expected a multipart/form-data body"))?;
+
+  let body = read_body(request)?;
+  let (file_name, content) = multipart_file(&body, &boundary, "file").ok_or(invalid(
+    "Multipart body contains no field \"file\" with a file name",
+  ))?;
+
+  let file_name = Path::new(&file_name)
+    .file_name()
+    .ok_or(invalid("Invalid file name"))?
+    .to_owned();
+
+  let upload_dir = std::env::temp_dir().join(format!("fdroid-upload-{}", Uuid::new_v4()));
+  fs::create_dir_all(&upload_dir)?;
+
+  let file_path = upload_dir.join(file_name);
+  let result = fs::write(&file_path, content)
+    .map_err(Error::from)
+    .and_then(|_| action(&file_path));
+
+  fs::remove_dir_all(&upload_dir)?;
+
+  result
+}
+
+/// Returns the file name and the content of a field of a `multipart/form-data` body
+pub(crate) fn multipart_file<'a>(
+  body: &'a [u8],
+  boundary: &str,
+  field_name: &str,
+) -> Option<(String, &'a [u8])> {
+  let delimiter = format!("--{boundary}");
+  let delimiter = delimiter.as_bytes();
+
+  let mut rest = body;
+
+  while let Some(start) = find(rest, delimiter) {
+    rest = &rest[start + delimiter.len()..];
+
+    // the last delimiter is followed by `--`
+    if rest.starts_with(b"--") {
+      break;
+    }
+
+    let part_end = find(rest, delimiter).unwrap_or(rest.len());
+    let part = rest[..part_end]
+      .strip_prefix(b"\r\n")
+      .unwrap_or(&rest[..part_end]);
+    let part = part.strip_suffix(b"\r\n").unwrap_or(part);
+
+    let header_end = find(part, b"\r\n\r\n")?;
+    let headers = String::from_utf8_lossy(&part[..header_end]);
+    let content = &part[header_end + 4..];
+
+    let disposition = headers
+      .lines()
+      .find(|line| {
+        line
+          .to_lowercase()
+          .starts_with("content-disposition: form-data")
+      })
+      .unwrap_or_default();
+
+    let parameter = |name: &str| {
+      disposition
+        .split(';')
+        .find_map(|parameter| parameter.trim().strip_prefix(&format!("{name}=")))
+        .map(|value| value.trim_matches('"').to_owned())
+    };
+
+    if parameter("name").as_deref() == Some(field_name) {
+      return Some((parameter("filename")?, content));
+    }
+  }
+
+  None
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+  haystack
+    .windows(needle.len())
+    .position(|window| window == needle)
+}
+
+fn header(name: &str, value: &str) -> Header {
+  Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("invalid header")
+}